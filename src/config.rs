@@ -0,0 +1,285 @@
+use std::fs;
+use std::str::FromStr;
+use async_std::io as aio;
+use clap::ArgMatches;
+use serde::Deserialize;
+use crate::contents;
+use crate::contents::Page;
+use crate::contents::PageRef;
+use crate::contents::MAX_PAGE_LINES;
+use crate::contents::TextFont;
+use crate::contents::TextLayout;
+
+pub const CANVASES: &[&str] = &["ssd1306", "simulator", "terminal", "print"];
+pub const INTERFACES: &[&str] = &["i2c", "spi"];
+pub const DISPLAY_SIZES: &[&str] = &["128x32", "128x64", "96x16"];
+pub const FONTS: &[&str] = &["6x8", "6x12", "8x16", "12x16"];
+
+/**
+ * settings are taken from `Config::default()`, then the `--config` toml file, then the command line
+ */
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: String,
+    pub canvas: String,
+    pub snapshot: String,
+    pub interface: String,
+    pub i2c_bus: String,
+    pub i2c_address: u8,
+    pub spi_bus: String,
+    pub gpio_chip: String,
+    pub spi_dc: u32,
+    pub spi_reset: Option<u32>,
+    pub page_roll_interval: u32,
+    pub marquee_step: u32,
+    pub marquee_pause: u32,
+    pub scroll_step: u32,
+    pub scroll_pause: u32,
+    #[serde(alias = "size")]
+    pub display_size: String,
+    pub font: String,
+    pub line_interval: u32,
+    pub state: Option<String>,
+    pub pages: Vec<PageConfig>,
+}
+
+/**
+ * an initial page, either `lines = [...]` or `image = "<base64>"` with `width` and `height`,
+ * optionally addressed by `name` and shown for `dwell` millisecond instead of the roll interval;
+ * with `wrap` the lines are joined into one paragraph that is word wrapped,
+ * with `marquee` lines longer than the display scroll,
+ * with `length` the page has that many lines and scrolls through those that do not fit
+ */
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PageConfig {
+    pub lines: Vec<String>,
+    pub image: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub name: Option<String>,
+    pub dwell: Option<u32>,
+    pub wrap: bool,
+    pub marquee: bool,
+    pub length: Option<usize>,
+}
+
+impl Default for Config {
+
+    fn default() -> Self {
+        Config {
+            bind: String::from("127.0.0.1:17900"),
+            canvas: String::from("ssd1306"),
+            snapshot: String::from("snapshot.png"),
+            interface: String::from("i2c"),
+            i2c_bus: String::from("/dev/i2c-1"),
+            i2c_address: 0x3C,
+            spi_bus: String::from("/dev/spidev0.0"),
+            gpio_chip: String::from("/dev/gpiochip0"),
+            spi_dc: 24,
+            spi_reset: None,
+            page_roll_interval: 5000,
+            marquee_step: 300,
+            marquee_pause: 1500,
+            scroll_step: 1000,
+            scroll_pause: 2000,
+            display_size: String::from("128x32"),
+            font: String::from("6x8"),
+            line_interval: 10,
+            state: None,
+            pages: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+
+    pub fn load(matches: &ArgMatches<'_>) -> aio::Result<Self> {
+        let mut config = match matches.value_of("config") {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.merge(matches)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &str) -> aio::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| aio::Error::other(format!("cannot read config {}: {}", path, e)))?;
+        toml::from_str(&text)
+            .map_err(|e| aio::Error::other(format!("invalid config {}: {}", path, e)))
+    }
+
+    fn merge(&mut self, matches: &ArgMatches<'_>) -> aio::Result<()> {
+        let strings = [
+            ("bind", &mut self.bind),
+            ("canvas", &mut self.canvas),
+            ("snapshot", &mut self.snapshot),
+            ("interface", &mut self.interface),
+            ("i2c_bus", &mut self.i2c_bus),
+            ("spi_bus", &mut self.spi_bus),
+            ("gpio_chip", &mut self.gpio_chip),
+            ("size", &mut self.display_size),
+            ("font", &mut self.font),
+        ];
+        for (name, field) in strings {
+            if let Some(s) = matches.value_of(name) {
+                *field = String::from(s);
+            }
+        }
+        if let Some(s) = matches.value_of("state") {
+            self.state = Some(String::from(s));
+        }
+        if let Some(s) = matches.value_of("i2c_address") {
+            self.i2c_address = parse_i2c_address(s)
+                .ok_or_else(|| aio::Error::other(format!("invalid i2c_address:{}", s)))?;
+        }
+        if let Some(s) = matches.value_of("spi_dc") {
+            self.spi_dc = parse_arg("spi_dc", s)?;
+        }
+        match matches.value_of("spi_reset") {
+            Some("none") => self.spi_reset = None,
+            Some(s) => self.spi_reset = Some(parse_arg("spi_reset", s)?),
+            None => {}
+        }
+        if let Some(s) = matches.value_of("page_roll_interval") {
+            self.page_roll_interval = parse_arg("page_roll_interval", s)?;
+        }
+        if let Some(s) = matches.value_of("marquee_step") {
+            self.marquee_step = parse_arg("marquee_step", s)?;
+        }
+        if let Some(s) = matches.value_of("marquee_pause") {
+            self.marquee_pause = parse_arg("marquee_pause", s)?;
+        }
+        if let Some(s) = matches.value_of("scroll_step") {
+            self.scroll_step = parse_arg("scroll_step", s)?;
+        }
+        if let Some(s) = matches.value_of("scroll_pause") {
+            self.scroll_pause = parse_arg("scroll_pause", s)?;
+        }
+        if let Some(s) = matches.value_of("line_interval") {
+            self.line_interval = parse_arg("line_interval", s)?;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> aio::Result<()> {
+        check_choice("canvas", &self.canvas, CANVASES)?;
+        check_choice("interface", &self.interface, INTERFACES)?;
+        check_choice("size", &self.display_size, DISPLAY_SIZES)?;
+        check_choice("font", &self.font, FONTS)?;
+        if self.page_roll_interval == 0 {
+            return Err(aio::Error::other("page_roll_interval must be greater than 0"));
+        }
+        if self.marquee_step == 0 {
+            return Err(aio::Error::other("marquee_step must be greater than 0"));
+        }
+        if self.scroll_step == 0 {
+            return Err(aio::Error::other("scroll_step must be greater than 0"));
+        }
+        if self.line_interval == 0 {
+            return Err(aio::Error::other("line_interval must be greater than 0"));
+        }
+        for (i, page) in self.pages.iter().enumerate() {
+            if let Some(name) = &page.name {
+                if name.is_empty() || PageRef::name_len(name.as_bytes()) != name.len() {
+                    return Err(aio::Error::other(format!("invalid page name:{}", name)));
+                }
+                if self.pages[..i].iter().any(|p| p.name.as_ref() == Some(name)) {
+                    return Err(aio::Error::other(format!("duplicate page name:{}", name)));
+                }
+            }
+            if let Some(length) = page.length.filter(|&length| length > MAX_PAGE_LINES) {
+                return Err(aio::Error::other(format!("page length {} is over {}", length, MAX_PAGE_LINES)));
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * how many lines of the default font fit on the display without being cut off at the bottom,
+     * and how wide a line is
+     */
+    pub fn text_layout(&self) -> TextLayout {
+        let (width, height) = parse_display_size(&self.display_size).unwrap_or((128, 32));
+        let font = TextFont::from_name(&self.font).unwrap_or(TextFont::Font6x8);
+        let (_, char_height) = font.size();
+        let lines = if height > char_height { (height - char_height) / self.line_interval + 1 } else { 1 };
+        TextLayout {
+            lines: lines as usize,
+            width,
+            height,
+            interval: self.line_interval,
+            font,
+        }
+    }
+}
+
+impl PageConfig {
+
+    pub fn to_page(&self, line_limit: usize, line_width: usize) -> aio::Result<Page> {
+        let line_limit = std::cmp::max(self.length.unwrap_or(0), line_limit);
+        if let Some(image) = &self.image {
+            let data = base64::decode(image)
+                .map_err(|e| aio::Error::other(format!("invalid page image: {}", e)))?;
+            let len = data.len();
+            let size = Page::image_size(self.width, self.height)
+                .ok_or_else(|| aio::Error::other(format!("page image of {}x{} is too large", self.width, self.height)))?;
+            Page::from_image(data, self.width, self.height)
+                .ok_or_else(|| aio::Error::other(format!(
+                    "page image of {}x{} needs {} bytes, got {}", self.width, self.height, size, len
+                )))
+        } else if self.wrap {
+            let (text, _) = contents::parse_markup(&self.lines.join(" "));
+            let mut page = Page::new_text(line_limit);
+            if page.set_paragraph(0, contents::wrap_text(&text, line_width)) {
+                Ok(page)
+            } else {
+                Err(aio::Error::other(format!("page text does not fit in {} lines", line_limit)))
+            }
+        } else if self.lines.len() > line_limit {
+            Err(aio::Error::other(format!("page has {} lines, at most {} fit", self.lines.len(), line_limit)))
+        } else {
+            let mut page = Page::new_text(line_limit);
+            for (i, line) in self.lines.iter().enumerate() {
+                let (text, spans) = contents::parse_markup(line);
+                if !self.marquee && text.chars().count() > line_width {
+                    return Err(aio::Error::other(format!("page line `{}` is longer than {} characters", line, line_width)));
+                }
+                page.set_marked_text(i, text, spans);
+            }
+            Ok(page)
+        }
+    }
+}
+
+pub fn parse_display_size(s: &str) -> Option<(u32, u32)> {
+    match s {
+        "128x32" => Some((128, 32)),
+        "128x64" => Some((128, 64)),
+        "96x16" => Some((96, 16)),
+        _ => None
+    }
+}
+
+fn parse_i2c_address(s: &str) -> Option<u8> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u8::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn parse_arg<T: FromStr>(name: &str, s: &str) -> aio::Result<T> {
+    s.parse().map_err(|_| aio::Error::other(format!("invalid {}:{}", name, s)))
+}
+
+fn check_choice(name: &str, value: &str, choices: &[&str]) -> aio::Result<()> {
+    if choices.contains(&value) {
+        Ok(())
+    } else {
+        Err(aio::Error::other(format!("unsupport {}:{}, can be `{}`", name, value, choices.join("`, `"))))
+    }
+}
@@ -1,30 +1,408 @@
-#[derive(Debug, Clone)]
+use std::fs;
+use std::io::Write as _;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::Instant;
+use async_std::io as aio;
+use serde::Deserialize;
+use serde::Serialize;
+
+/**
+ * the most lines a long page can have
+ */
+pub const MAX_PAGE_LINES: usize = 1000;
+
+/**
+ * the most samples a chart keeps
+ */
+pub const MAX_CHART_SAMPLES: usize = 1000;
+
+/**
+ * the built-in fonts, named by their character cell like on the command line
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextFont {
+    #[serde(rename = "6x8")]
+    Font6x8,
+    #[serde(rename = "6x12")]
+    Font6x12,
+    #[serde(rename = "8x16")]
+    Font8x16,
+    #[serde(rename = "12x16")]
+    Font12x16,
+}
+
+impl TextFont {
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "6x8" => Some(Self::Font6x8),
+            "6x12" => Some(Self::Font6x12),
+            "8x16" => Some(Self::Font8x16),
+            "12x16" => Some(Self::Font12x16),
+            _ => None
+        }
+    }
+
+    /**
+     * width and height of a character
+     */
+    pub fn size(self) -> (u32, u32) {
+        match self {
+            Self::Font6x8 => (6, 8),
+            Self::Font6x12 => (6, 12),
+            Self::Font8x16 => (8, 16),
+            Self::Font12x16 => (12, 16),
+        }
+    }
+}
+
+/**
+ * where a line sits horizontally
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+
+    fn is_left(&self) -> bool {
+        *self == Self::Left
+    }
+
+    /**
+     * x of a line `len` pixels long on a line `width` pixels wide, lines too long start at 0
+     */
+    pub fn offset(self, len: u32, width: u32) -> u32 {
+        let room = width.saturating_sub(len);
+        match self {
+            Self::Left => 0,
+            Self::Center => room / 2,
+            Self::Right => room,
+        }
+    }
+}
+
+/**
+ * highlighting of a span of text
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attrs {
+    #[serde(default)]
+    pub invert: bool,
+    #[serde(default)]
+    pub underline: bool,
+    #[serde(default)]
+    pub blink: bool,
+}
+
+/**
+ * characters `start..end` of a line drawn with `attrs`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub attrs: Attrs,
+}
+
+/**
+ * a progress bar for `value` between `min` and `max`, drawn after the text of its line
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bar {
+    pub value: i32,
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Bar {
+
+    /**
+     * how much of the bar is filled, from 0 to 1
+     */
+    pub fn fraction(&self) -> f32 {
+        let span = self.max as f64 - self.min as f64;
+        if span <= 0.0 {
+            return 0.0;
+        }
+        ((self.value as f64 - self.min as f64) / span).clamp(0.0, 1.0) as f32
+    }
+}
+
+/**
+ * how a line of a text page is drawn
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LineStyle {
+    /// `None` for the default font
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font: Option<TextFont>,
+    #[serde(default, skip_serializing_if = "Align::is_left")]
+    pub align: Align,
+    /// highlighted parts of the text, set with the markup of `parse_markup`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<Span>,
+    /// the line is a progress bar labelled with its text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bar: Option<Bar>,
+}
+
+impl LineStyle {
+
+    /**
+     * the style of the characters `offset..offset+width` of the line, for a line cut to them
+     */
+    pub fn window(&self, offset: usize, width: usize) -> Self {
+        let end = offset + width;
+        let spans = self.spans.iter()
+            .filter(|span| span.start < end && span.end > offset)
+            .map(|span| Span {
+                start: span.start.saturating_sub(offset),
+                end: std::cmp::min(span.end, end) - offset,
+                attrs: span.attrs,
+            })
+            .collect();
+        LineStyle {
+            spans,
+            ..self.clone()
+        }
+    }
+
+    pub fn has_blink(&self) -> bool {
+        self.spans.iter().any(|span| span.attrs.blink)
+    }
+
+    /**
+     * drop what belonged to the text of the line: its spans and its bar
+     */
+    fn text_changed(&mut self) {
+        self.spans.clear();
+        self.bar = None;
+    }
+}
+
+/**
+ * how text fits on the display: `lines` lines of the default `font` per page, `width` by `height` pixels,
+ * a line starting every `interval` pixels
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextLayout {
+    pub lines: usize,
+    pub width: u32,
+    pub height: u32,
+    pub interval: u32,
+    pub font: TextFont,
+}
+
+impl TextLayout {
+
+    /**
+     * characters that fit on a line in `font`, or in the default font for `None`
+     */
+    pub fn columns(&self, font: Option<TextFont>) -> usize {
+        (self.width / font.unwrap_or(self.font).size().0) as usize
+    }
+
+    /**
+     * pixels taken by lines in `fonts` drawn one below the other, keeping the gap `interval` leaves under the default font
+     */
+    pub fn text_height(&self, fonts: impl Iterator<Item=Option<TextFont>>) -> u32 {
+        let gap = self.interval as i32 - self.font.size().1 as i32;
+        let height = fonts
+            .map(|font| font.unwrap_or(self.font).size().1 as i32)
+            .reduce(|above, below| above + gap + below)
+            .unwrap_or(0);
+        std::cmp::max(height, 0) as u32
+    }
+}
+
+/**
+ * how the samples of a chart are drawn
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartKind {
+    #[default]
+    Line,
+    Bars,
+}
+
+/**
+ * the last `capacity` samples of a time series, the oldest ones drop out as new ones come in
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chart {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub kind: ChartKind,
+    pub capacity: usize,
+    pub samples: VecDeque<f32>,
+}
+
+impl Chart {
+
+    pub fn new(capacity: usize) -> Self {
+        Chart {
+            title: None,
+            kind: ChartKind::default(),
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, value: f32) {
+        while self.samples.len() >= self.capacity.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+
+    /**
+     * keep at most `capacity` samples from now on, dropping the oldest ones
+     */
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.samples.len() > capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    /**
+     * lowest and highest sample, widened when they are equal so a flat series has a scale
+     */
+    pub fn range(&self) -> Option<(f32, f32)> {
+        let lo = self.samples.iter().copied().reduce(f32::min)?;
+        let hi = self.samples.iter().copied().reduce(f32::max)?;
+        if (hi - lo).abs() < f32::EPSILON {
+            Some((lo - 1.0, hi + 1.0))
+        } else {
+            Some((lo, hi))
+        }
+    }
+}
+
+/**
+ * whole numbers without a fraction, the rest with one decimal
+ */
+pub fn format_sample(value: f32) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e9 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Page {
     Empty,
-    Text { lines: Vec<String> },
-    BImage { data: Box<[u8]>, w: u32, h: u32 },
+    Text {
+        lines: Vec<String>,
+        /// style of each line, missing at the end for the default style
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        styles: Vec<LineStyle>,
+    },
+    BImage {
+        #[serde(with = "base64_data")]
+        data: Box<[u8]>,
+        w: u32,
+        h: u32
+    },
+    Chart(Chart),
 }
 
 impl Page {
 
     pub fn new_text(line_num: usize) -> Self {
         Self::Text {
-            lines: (0 .. line_num).map(|_i| String::default()).collect()
+            lines: (0 .. line_num).map(|_i| String::default()).collect(),
+            styles: Vec::new(),
+        }
+    }
+
+    pub fn from_image(data: Vec<u8>, w: u32, h: u32) -> Option<Self> {
+        if Some(data.len()) == Self::image_size(w, h) {
+            Some(Self::BImage {
+                data: data.into_boxed_slice(),
+                w,
+                h
+            })
+        } else {
+            None
         }
     }
 
-    pub fn new_image(w: u32, h: u32) -> Self {
-        Self::BImage {
-            data: (0 .. ((w + 7) / 8) * h).map(|i| 0u8).collect(),
-            w,
-            h
+    /// bytes needed by a `w`x`h` bitmap: rows are padded to whole bytes, MSB first; `None` if that does not fit in memory
+    pub fn image_size(w: u32, h: u32) -> Option<usize> {
+        (w.div_ceil(8) as usize).checked_mul(h as usize)
+    }
+
+    /**
+     * what is wrong with a page read from a state file, which may have been edited or damaged
+     */
+    fn check(&self) -> Result<(), String> {
+        match self {
+            Self::BImage{ data, w, h } if Self::image_size(*w, *h) != Some(data.len()) => {
+                Err(format!("image of {}x{} with {} bytes", w, h, data.len()))
+            }
+            Self::Chart(chart) if chart.capacity == 0 || chart.capacity > MAX_CHART_SAMPLES => {
+                Err(format!("chart of {} samples, 1 to {}", chart.capacity, MAX_CHART_SAMPLES))
+            }
+            _ => Ok(())
         }
     }
 
+    /**
+     * replace line `i`, dropping the highlighting and bar of the old text
+     */
     pub fn set_text(&mut self, i: usize, text: String) -> bool {
-        if let Self::Text{ lines } = self {
+        if let Self::Text{ lines, styles } = self {
             if let Some(line) = lines.get_mut(i) {
                 *line = text;
+                if let Some(style) = styles.get_mut(i) {
+                    style.text_changed();
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /**
+     * replace line `i` with text highlighted by `spans`
+     */
+    pub fn set_marked_text(&mut self, i: usize, text: String, spans: Vec<Span>) -> bool {
+        if !self.set_text(i, text) {
+            return false;
+        }
+        if !spans.is_empty() {
+            if let Some(style) = self.style_mut(i) {
+                style.spans = spans;
+            }
+        }
+        true
+    }
+
+    /**
+     * put the wrapped `lines` of a paragraph from line `i` on and clear the lines after it
+     */
+    pub fn set_paragraph(&mut self, i: usize, paragraph: Vec<String>) -> bool {
+        if let Self::Text{ lines, styles } = self {
+            if i < lines.len() && i + paragraph.len() <= lines.len() {
+                let rest = lines.len() - i - paragraph.len();
+                let tail = paragraph.into_iter().chain((0 .. rest).map(|_| String::default()));
+                for (line, text) in lines[i..].iter_mut().zip(tail) {
+                    *line = text;
+                }
+                for style in styles.iter_mut().skip(i) {
+                    style.text_changed();
+                }
                 return true;
             }
         }
@@ -32,24 +410,139 @@ impl Page {
     }
 
     pub fn get_text(&self, i: usize) -> Option<&str> {
-        if let Self::Text{ lines } = self {
+        if let Self::Text{ lines, .. } = self {
             return lines.get(i).map(String::as_str);
         }
         None
     }
 
+    /**
+     * whether line `i` draws nothing: no text and no bar
+     */
+    pub fn is_blank(&self, i: usize) -> bool {
+        self.get_text(i).is_none_or(str::is_empty) && self.style(i).bar.is_none()
+    }
+
+    /**
+     * turn line `i` into a progress bar, labelled with `label` or the text already on the line
+     */
+    pub fn set_bar(&mut self, i: usize, label: Option<String>, bar: Bar) -> bool {
+        if let Some(label) = label {
+            if !self.set_text(i, label) {
+                return false;
+            }
+        }
+        match self.style_mut(i) {
+            Some(style) => {
+                style.bar = Some(bar);
+                true
+            }
+            None => false
+        }
+    }
+
+    /**
+     * style of line `i`, the default one for lines that never got their own
+     */
+    pub fn style(&self, i: usize) -> LineStyle {
+        if let Self::Text{ styles, .. } = self {
+            return styles.get(i).cloned().unwrap_or_default();
+        }
+        LineStyle::default()
+    }
+
+    /**
+     * font of line `i`, `None` for the default one
+     */
+    pub fn font(&self, i: usize) -> Option<TextFont> {
+        self.style(i).font
+    }
+
+    /**
+     * style of an existing line `i` to be changed
+     */
+    pub fn style_mut(&mut self, i: usize) -> Option<&mut LineStyle> {
+        if let Self::Text{ lines, styles } = self {
+            if i < lines.len() {
+                if styles.len() <= i {
+                    styles.resize_with(i + 1, LineStyle::default);
+                }
+                return styles.get_mut(i);
+            }
+        }
+        None
+    }
+
+    /**
+     * make a text page `n` lines long, dropping lines at the end or adding empty ones
+     */
+    pub fn resize_text(&mut self, n: usize) {
+        if let Self::Text{ lines, styles } = self {
+            lines.resize_with(n, String::default);
+            styles.truncate(n);
+        }
+    }
+
+    /**
+     * remove line `i` and scroll the lines below it up, an empty line comes in at the bottom
+     */
     pub fn remove_text(&mut self, i: usize) -> bool {
-        if let Self::Text{ lines } = self {
+        if let Self::Text{ lines, styles } = self {
             if i < lines.len() {
                 lines.remove(i);
+                lines.push(String::default());
+                if i < styles.len() {
+                    styles.remove(i);
+                }
                 return true;
             }
         }
         false
     }
 
+    /**
+     * put `text` at line `i` and scroll the lines from there down, the last line drops out
+     */
+    pub fn insert_text(&mut self, i: usize, text: String) -> bool {
+        if let Self::Text{ lines, styles } = self {
+            if i < lines.len() {
+                lines.pop();
+                lines.insert(i, text);
+                if i < styles.len() {
+                    styles.insert(i, LineStyle::default());
+                    styles.truncate(lines.len());
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Empty => "Empty",
+            Self::Text{ .. } => "Text",
+            Self::BImage{ .. } => "BImage",
+            Self::Chart(_) => "Chart",
+        }
+    }
+
+    /**
+     * the chart of a chart page, an empty page becomes a chart keeping `capacity` samples;
+     * `None` for text and image pages
+     */
+    pub fn as_chart(&mut self, capacity: usize) -> Option<&mut Chart> {
+        if let Self::Empty = self {
+            *self = Self::Chart(Chart::new(capacity));
+        }
+        match self {
+            Self::Chart(chart) => Some(chart),
+            _ => None
+        }
+    }
+
     pub fn line_num(&self) -> usize {
-        if let Self::Text{ lines } = self {
+        if let Self::Text{ lines, .. } = self {
             lines.len()
         } else {
             0
@@ -58,37 +551,239 @@ impl Page {
 }
 
 
+/**
+ * how a client addresses a page: its position in the rotation or its stable name
+ */
+#[derive(Debug, Clone)]
+pub enum PageRef {
+    Index(usize),
+    Name(String),
+}
+
+impl PageRef {
+
+    /**
+     * length of the name at the start of `s`, 0 if there is none;
+     * a name starts with a letter or `_` and goes on with letters, digits, `_`, `-` and `.`
+     */
+    pub fn name_len(s: &[u8]) -> usize {
+        match s.first() {
+            Some(c) if c.is_ascii_alphabetic() || *c == b'_' => {
+                s.iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == b'_' || **c == b'-' || **c == b'.')
+                    .count()
+            }
+            _ => 0
+        }
+    }
+}
+
+/**
+ * a page together with the settings of its slot in the rotation;
+ * expiry times are unix time in millisecond so they survive a restart
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    page: Page,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    dwell: Option<u32>,
+    #[serde(default)]
+    wrap: bool,
+    #[serde(default)]
+    marquee: bool,
+    #[serde(default)]
+    length: Option<usize>,
+    /// saved as the millisecond left, the deadline itself only holds while the program runs
+    #[serde(default, rename = "ttl", with = "time_left")]
+    expires: Option<Instant>,
+    #[serde(default, rename = "line_ttl", skip_serializing_if = "Vec::is_empty", with = "time_left::lines")]
+    line_expires: Vec<Option<Instant>>,
+    #[serde(skip)]
+    id: u64,
+}
+
+impl Entry {
+
+    fn new(page: Page, id: u64) -> Self {
+        Entry {
+            page,
+            name: None,
+            dwell: None,
+            wrap: false,
+            marquee: false,
+            length: None,
+            expires: None,
+            line_expires: Vec::new(),
+            id,
+        }
+    }
+
+    /**
+     * resize a text page to its length, but at least `line_limit` lines
+     */
+    fn fit_lines(&mut self, line_limit: usize) {
+        let n = std::cmp::max(self.length.unwrap_or(0), line_limit);
+        self.page.resize_text(n);
+        self.line_expires.truncate(n);
+    }
+
+    /**
+     * what is wrong with an entry read from a state file, which may have been edited or damaged
+     */
+    fn check(&self) -> Result<(), String> {
+        self.page.check()?;
+        match self.length {
+            Some(length) if length > MAX_PAGE_LINES => Err(format!("{} lines, at most {}", length, MAX_PAGE_LINES)),
+            _ => Ok(())
+        }
+    }
+
+    /**
+     * the earliest time the page or one of its lines expires
+     */
+    fn next_expiry(&self) -> Option<Instant> {
+        self.line_expires.iter()
+            .flatten()
+            .chain(self.expires.iter())
+            .min()
+            .copied()
+    }
+}
+
 pub struct Content {
-    pages: Vec<Page>,
-    line_limit: usize
+    pages: Vec<Entry>,
+    layout: TextLayout,
+    next_id: u64,
 }
 
 impl Content {
 
-    pub fn new(line_limit: usize) -> Self {
+    pub fn new(layout: TextLayout) -> Self {
         Content {
             pages: Vec::new(),
-            line_limit,
+            layout,
+            next_id: 0,
         }
     }
 
-    pub fn new_with_capacity(line_limit: usize, capacity: usize) -> Self {
+    #[allow(dead_code)]
+    pub fn new_with_capacity(layout: TextLayout, capacity: usize) -> Self {
         Content {
             pages: Vec::with_capacity(capacity),
-            line_limit
+            layout,
+            next_id: 0,
         }
     }
 
     pub fn line_limit(&self) -> usize {
-        self.line_limit
+        self.layout.lines
+    }
+
+    /**
+     * samples a new chart keeps: one per pixel column
+     */
+    pub fn chart_capacity(&self) -> usize {
+        self.layout.width as usize
+    }
+
+    /**
+     * characters that fit on a line in the default font
+     */
+    pub fn line_width(&self) -> usize {
+        self.layout.columns(None)
+    }
+
+    /**
+     * characters that fit on a line in `font`, `None` for the default font
+     */
+    pub fn font_width(&self, font: Option<TextFont>) -> usize {
+        self.layout.columns(font)
+    }
+
+    /**
+     * characters that fit on line `line` of page `i`, following the font of that line
+     */
+    pub fn line_width_at(&self, i: usize, line: usize) -> usize {
+        let font = self.pages.get(i).and_then(|e| e.page.font(line));
+        self.font_width(font)
+    }
+
+    /**
+     * whether `page` fits on the display in the fonts of its lines, wherever a long page is scrolled to;
+     * if not, how many pixels it needs and how many there are.
+     * blank lines at the bottom may be pushed off the display by a taller line above them
+     */
+    pub fn check_height(&self, page: &Page) -> Result<(), (u32, u32)> {
+        let n = page.line_num();
+        let limit = std::cmp::min(self.layout.lines, n);
+        let height = (0 ..= n - limit)
+            .map(|top| {
+                let end = (top .. top + limit).rev()
+                    .find(|&k| !page.is_blank(k))
+                    .map_or(top, |k| k + 1);
+                self.layout.text_height((top .. end).map(|k| page.font(k)))
+            })
+            .max()
+            .unwrap_or(0);
+        if height > self.layout.height {
+            Err((height, self.layout.height))
+        } else {
+            Ok(())
+        }
+    }
+
+    /**
+     * fit all text pages to the new number of lines, returns whether that changed anything
+     */
+    pub fn set_text_layout(&mut self, layout: TextLayout) -> bool {
+        let changed = layout.lines != self.layout.lines;
+        self.layout = layout;
+        if changed {
+            self.fit_lines();
+        }
+        changed
+    }
+
+    fn fit_lines(&mut self) {
+        let line_limit = self.layout.lines;
+        for entry in self.pages.iter_mut() {
+            entry.fit_lines(line_limit);
+        }
+    }
+
+    /**
+     * lines of text page `i`, more than fit on the display if it is a long page;
+     * a page yet to be added gets `line_limit`
+     */
+    pub fn page_lines(&self, i: usize) -> usize {
+        match self.pages.get(i).and_then(|e| e.length) {
+            Some(length) => std::cmp::max(length, self.layout.lines),
+            None => self.layout.lines
+        }
+    }
+
+    /**
+     * make page `i` a long page of `length` lines that scrolls, `None` fits it to the display again
+     */
+    pub fn set_length(&mut self, i: usize, length: Option<usize>) -> bool {
+        let line_limit = self.layout.lines;
+        if let Some(entry) = self.pages.get_mut(i) {
+            entry.length = length;
+            entry.fit_lines(line_limit);
+            true
+        } else {
+            false
+        }
     }
 
     pub fn get(&self, i: usize) -> Option<&Page> {
-        self.pages.get(i)
+        self.pages.get(i).map(|e| &e.page)
     }
 
     pub fn get_mut(&mut self, i: usize) -> Option<&mut Page> {
-        self.pages.get_mut(i)
+        self.pages.get_mut(i).map(|e| &mut e.page)
     }
 
     pub fn get_mut_or_add<G: FnOnce()->Page>(&mut self, i: usize, generate: G) -> Option<&mut Page> {
@@ -97,24 +792,29 @@ impl Content {
             None
         } else {
             if i == len {
-                let page = generate();
-                self.pages.push(page);
+                let entry = self.entry(generate());
+                self.pages.push(entry);
             } 
-            Some(unsafe{ self.pages.get_unchecked_mut(i) })
+            Some(unsafe{ &mut self.pages.get_unchecked_mut(i).page })
         }
     }
 
+    /**
+     * the slot keeps its settings (like the dwell time) when its page is replaced,
+     * only the line expiry is dropped since the lines are gone
+     */
     pub fn set(&mut self, i: usize, page: Page) -> bool {
         let len = self.pages.len();
         if i > len {
             false
         } else {
             if i == len {
-                self.pages.push(page);
+                let entry = self.entry(page);
+                self.pages.push(entry);
             } else {
-                unsafe {
-                    *(self.pages.get_unchecked_mut(i)) = page;
-                }
+                let entry = unsafe { self.pages.get_unchecked_mut(i) };
+                entry.page = page;
+                entry.line_expires.clear();
             }
             true
         }
@@ -122,7 +822,7 @@ impl Content {
 
     pub fn remove(&mut self, i: usize) -> Option<Page> {
         if i < self.pages.len() {
-            Some(self.pages.remove(i))
+            Some(self.pages.remove(i).page)
         } else {
             None
         }
@@ -131,4 +831,566 @@ impl Content {
     pub fn len(&self) -> usize {
         self.pages.len()
     }
+
+    /**
+     * put `page` before page `i` (or at the end if `i` is the page count), shifting the later ones
+     */
+    pub fn insert(&mut self, i: usize, page: Page) -> bool {
+        if i > self.pages.len() {
+            false
+        } else {
+            let entry = self.entry(page);
+            self.pages.insert(i, entry);
+            true
+        }
+    }
+
+    /**
+     * take page `from` out and put it back so it ends up at position `to`, with its settings
+     */
+    pub fn move_page(&mut self, from: usize, to: usize) -> bool {
+        let len = self.pages.len();
+        if from >= len || to >= len {
+            false
+        } else {
+            let entry = self.pages.remove(from);
+            self.pages.insert(to, entry);
+            true
+        }
+    }
+
+    pub fn swap(&mut self, i: usize, j: usize) -> bool {
+        let len = self.pages.len();
+        if i >= len || j >= len {
+            false
+        } else {
+            self.pages.swap(i, j);
+            true
+        }
+    }
+
+    /**
+     * an identity for page `i` that follows it when pages are inserted, moved or removed
+     * around it, it is not saved and only stable while the process runs
+     */
+    pub fn id(&self, i: usize) -> Option<u64> {
+        self.pages.get(i).map(|e| e.id)
+    }
+
+    /**
+     * where the page with identity `id` is now
+     */
+    pub fn position(&self, id: u64) -> Option<usize> {
+        self.pages.iter().position(|e| e.id == id)
+    }
+
+    fn entry(&mut self, page: Page) -> Entry {
+        self.next_id += 1;
+        Entry::new(page, self.next_id)
+    }
+
+    /**
+     * position of the page named `name`
+     */
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.pages.iter().position(|e| e.name.as_deref() == Some(name))
+    }
+
+    pub fn name(&self, i: usize) -> Option<&str> {
+        self.pages.get(i).and_then(|e| e.name.as_deref())
+    }
+
+    /**
+     * give page `i` its name, `None` leaves the current one
+     */
+    pub fn set_name(&mut self, i: usize, name: Option<String>) {
+        if let (Some(entry), Some(name)) = (self.pages.get_mut(i), name) {
+            entry.name = Some(name);
+        }
+    }
+
+    /**
+     * how long page `i` stays in the rotation in millisecond, `None` for the roll interval
+     */
+    pub fn dwell(&self, i: usize) -> Option<u32> {
+        self.pages.get(i).and_then(|e| e.dwell)
+    }
+
+    pub fn set_dwell(&mut self, i: usize, dwell: Option<u32>) -> bool {
+        if let Some(entry) = self.pages.get_mut(i) {
+            entry.dwell = dwell;
+            true
+        } else {
+            false
+        }
+    }
+
+    /**
+     * whether text written to page `i` is word wrapped over the following lines
+     */
+    pub fn wrap(&self, i: usize) -> bool {
+        self.pages.get(i).is_some_and(|e| e.wrap)
+    }
+
+    pub fn set_wrap(&mut self, i: usize, wrap: bool) -> bool {
+        if let Some(entry) = self.pages.get_mut(i) {
+            entry.wrap = wrap;
+            true
+        } else {
+            false
+        }
+    }
+
+    /**
+     * whether lines of page `i` that are too long scroll instead of being refused
+     */
+    pub fn marquee(&self, i: usize) -> bool {
+        self.pages.get(i).is_some_and(|e| e.marquee)
+    }
+
+    pub fn set_marquee(&mut self, i: usize, marquee: bool) -> bool {
+        if let Some(entry) = self.pages.get_mut(i) {
+            entry.marquee = marquee;
+            true
+        } else {
+            false
+        }
+    }
+
+    /**
+     * page `i` (or only its `line`) expires at `at`, `None` keeps it forever
+     */
+    pub fn set_expiry(&mut self, i: usize, line: Option<usize>, at: Option<Instant>) -> bool {
+        let entry = match self.pages.get_mut(i) {
+            Some(entry) => entry,
+            None => return false
+        };
+        match line {
+            None => entry.expires = at,
+            Some(line) => {
+                if line >= entry.page.line_num() {
+                    return false;
+                }
+                if entry.line_expires.len() <= line {
+                    entry.line_expires.resize(line + 1, None);
+                }
+                entry.line_expires[line] = at;
+            }
+        }
+        true
+    }
+
+    /**
+     * keep the line expiry in step after line `line` of page `i` was removed
+     */
+    pub fn line_removed(&mut self, i: usize, line: usize) {
+        if let Some(entry) = self.pages.get_mut(i) {
+            if line < entry.line_expires.len() {
+                entry.line_expires.remove(line);
+            }
+        }
+    }
+
+    /**
+     * keep the line expiry in step after a line was inserted at `line` of page `i`
+     */
+    pub fn line_inserted(&mut self, i: usize, line: usize) {
+        if let Some(entry) = self.pages.get_mut(i) {
+            if line < entry.line_expires.len() {
+                entry.line_expires.insert(line, None);
+                entry.line_expires.truncate(entry.page.line_num());
+            }
+        }
+    }
+
+    /**
+     * the earliest time something on page `i` expires
+     */
+    pub fn expiry(&self, i: usize) -> Option<Instant> {
+        self.pages.get(i).and_then(Entry::next_expiry)
+    }
+
+    /**
+     * the earliest time something on any page expires
+     */
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.pages.iter().filter_map(Entry::next_expiry).min()
+    }
+
+    /**
+     * remove the pages and clear the lines whose time is up at `now`;
+     * returns whether anything changed
+     */
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let len = self.pages.len();
+        self.pages.retain(|entry| entry.expires.is_none_or(|at| at > now));
+        let mut changed = self.pages.len() != len;
+        for entry in self.pages.iter_mut() {
+            for (line, at) in entry.line_expires.iter_mut().enumerate() {
+                if at.is_some_and(|at| at <= now) {
+                    *at = None;
+                    entry.page.set_text(line, String::default());
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /**
+     * replace all pages with the ones saved by `save`, text pages are fitted to `line_limit`
+     * or their own length;
+     * returns false if there is no saved state yet, a damaged state is an `InvalidData` error
+     * and leaves the pages as they were
+     */
+    pub fn load(&mut self, path: &Path) -> aio::Result<bool> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == aio::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let damaged = |e: String| aio::Error::new(aio::ErrorKind::InvalidData, format!("invalid state {}: {}", path.display(), e));
+        let pages: Vec<Entry> = serde_json::from_str(&text)
+            .map_err(|e| damaged(e.to_string()))?;
+        for (i, entry) in pages.iter().enumerate() {
+            entry.check()
+                .map_err(|e| damaged(format!("page {}: {}", i, e)))?;
+        }
+        self.pages = pages;
+        for entry in self.pages.iter_mut() {
+            self.next_id += 1;
+            entry.id = self.next_id;
+        }
+        self.fit_lines();
+        Ok(true)
+    }
+
+    /**
+     * written to a temporary file first so a power loss never leaves a truncated state
+     */
+    pub fn save(&self, path: &Path) -> aio::Result<()> {
+        let text = serde_json::to_string(&self.pages)
+            .map_err(|e| aio::Error::other(format!("{}", e)))?;
+        let tmp = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(text.as_bytes())?;
+        // on the disk before the rename, or the renamed file may come back empty
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/**
+ * split `{<flags>:<text>}` spans off `text`, flags being any of `i` (inverted), `u` (underlined)
+ * and `b` (blinking); braces that do not form a span are kept as they are
+ */
+pub fn parse_markup(text: &str) -> (String, Vec<Span>) {
+    let mut plain = String::with_capacity(text.len());
+    let mut spans = Vec::new();
+    let mut len = 0;
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        let (before, from) = rest.split_at(open);
+        plain.push_str(before);
+        len += before.chars().count();
+        match parse_span(&from[1..]) {
+            Some((attrs, inner, after)) => {
+                let n = inner.chars().count();
+                plain.push_str(inner);
+                spans.push(Span{ start: len, end: len + n, attrs });
+                len += n;
+                rest = after;
+            }
+            None => {
+                plain.push('{');
+                len += 1;
+                rest = &from[1..];
+            }
+        }
+    }
+    plain.push_str(rest);
+    (plain, spans)
+}
+
+/**
+ * the flags, text and rest after the closing brace of a span whose `{` is already taken
+ */
+fn parse_span(s: &str) -> Option<(Attrs, &str, &str)> {
+    let colon = s.find(':')?;
+    let (flags, body) = (&s[..colon], &s[colon+1..]);
+    if flags.is_empty() {
+        return None;
+    }
+    let mut attrs = Attrs::default();
+    for c in flags.chars() {
+        match c {
+            'i' => attrs.invert = true,
+            'u' => attrs.underline = true,
+            'b' => attrs.blink = true,
+            _ => return None
+        }
+    }
+    let close = body.find('}')?;
+    Some((attrs, &body[..close], &body[close+1..]))
+}
+
+/**
+ * break `text` into lines of at most `width` characters on spaces,
+ * words longer than a line are split; there is always at least one line
+ */
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    wrap_text_by(text, |_| width)
+}
+
+/**
+ * like `wrap_text`, but line `k` of the paragraph holds `width(k)` characters
+ */
+pub fn wrap_text_by<F: Fn(usize) -> usize>(text: &str, width: F) -> Vec<String> {
+    let room = |k: usize| std::cmp::max(width(k), 1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut len = 0;
+    let mut width = room(0);
+    for word in text.split_whitespace() {
+        let mut chars: Vec<char> = word.chars().collect();
+        if len > 0 && len + 1 + chars.len() > width {
+            lines.push(std::mem::take(&mut line));
+            len = 0;
+            width = room(lines.len());
+        }
+        while chars.len() > width - len {
+            let rest = chars.split_off(width - len);
+            line.extend(chars);
+            lines.push(std::mem::take(&mut line));
+            len = 0;
+            width = room(lines.len());
+            chars = rest;
+        }
+        if len > 0 {
+            line.push(' ');
+            len += 1;
+        }
+        len += chars.len();
+        line.extend(chars);
+    }
+    if len > 0 || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+mod base64_data {
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serializer;
+    use serde::de::Error as _;
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Box<[u8]>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        base64::decode(&s)
+            .map(Vec::into_boxed_slice)
+            .map_err(D::Error::custom)
+    }
+}
+
+/**
+ * deadlines as the millisecond left until them, counting from when they are read back
+ */
+mod time_left {
+    use std::time::Duration;
+    use std::time::Instant;
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serialize;
+    use serde::Serializer;
+
+    fn left(at: &Option<Instant>) -> Option<u64> {
+        at.map(|at| at.saturating_duration_since(Instant::now()).as_millis() as u64)
+    }
+
+    fn from_now<E: serde::de::Error>(ms: Option<u64>) -> Result<Option<Instant>, E> {
+        ms.map(|ms| Instant::now().checked_add(Duration::from_millis(ms)).ok_or_else(|| E::custom("ttl too large")))
+            .transpose()
+    }
+
+    pub fn serialize<S: Serializer>(at: &Option<Instant>, serializer: S) -> Result<S::Ok, S::Error> {
+        left(at).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Instant>, D::Error> {
+        from_now(Option::<u64>::deserialize(deserializer)?)
+    }
+
+    pub mod lines {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(at: &[Option<Instant>], serializer: S) -> Result<S::Ok, S::Error> {
+            at.iter().map(left).collect::<Vec<_>>().serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Option<Instant>>, D::Error> {
+            Vec::<Option<u64>>::deserialize(deserializer)?
+                .into_iter()
+                .map(from_now)
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> TextLayout {
+        TextLayout{ lines: 3, width: 128, height: 32, interval: 10, font: TextFont::Font6x8 }
+    }
+
+    /// a state file only this test writes
+    fn state_file(name: &str, text: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("ssd1306-{}-{}.json", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_restores_saved_pages() {
+        let path = state_file("saved", "");
+        let mut content = Content::new(layout());
+        content.set(0, Page::from_image(vec![0xff; 2], 8, 2).unwrap());
+        content.save(&path).unwrap();
+        let mut loaded = Content::new(layout());
+        assert!(loaded.load(&path).unwrap());
+        assert!(matches!(loaded.get(0), Some(Page::BImage{ w: 8, h: 2, .. })));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_keeps_the_time_left_to_expire() {
+        let path = state_file("ttl", "");
+        let mut content = Content::new(layout());
+        content.set(0, Page::new_text(3));
+        content.set_expiry(0, None, Some(Instant::now() + std::time::Duration::from_secs(60)));
+        content.set_expiry(0, Some(1), Some(Instant::now() + std::time::Duration::from_secs(5)));
+        content.save(&path).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!((59_000 ..= 60_000).contains(&saved[0]["ttl"].as_u64().unwrap()), "{}", saved);
+        assert!((4_000 ..= 5_000).contains(&saved[0]["line_ttl"][1].as_u64().unwrap()), "{}", saved);
+        let mut loaded = Content::new(layout());
+        assert!(loaded.load(&path).unwrap());
+        let left = loaded.next_expiry().unwrap().saturating_duration_since(Instant::now());
+        assert!(left.as_secs() >= 3 && left.as_secs() <= 5, "{:?}", left);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_damaged_entries() {
+        let damaged = [
+            ("size", r#"[{"page":{"BImage":{"data":"AAAA","w":128,"h":32}}}]"#),
+            ("truncated", r#"[{"page":{"Text":{"lines":["a""#),
+            ("length", r#"[{"page":{"Text":{"lines":["a"]}},"length":1001}]"#),
+            ("chart", r#"[{"page":{"Chart":{"capacity":0,"samples":[]}}}]"#),
+        ];
+        for (name, text) in damaged {
+            let path = state_file(name, text);
+            let e = Content::new(layout()).load(&path).unwrap_err();
+            assert_eq!(e.kind(), aio::ErrorKind::InvalidData, "{}: {}", name, e);
+            assert!(e.to_string().starts_with("invalid state"), "{}: {}", name, e);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    /// a 12x16 headline over `details` lines in the default font
+    fn headline(lines: usize, details: &[&str]) -> Page {
+        let mut page = Page::new_text(lines);
+        page.set_text(0, String::from("title"));
+        page.style_mut(0).unwrap().font = Some(TextFont::Font12x16);
+        for (k, detail) in details.iter().enumerate() {
+            page.set_text(k + 1, String::from(*detail));
+        }
+        page
+    }
+
+    #[test]
+    fn check_height_lets_a_headline_push_blank_lines_off() {
+        let content = Content::new(layout());
+        assert_eq!(content.check_height(&headline(3, &[])), Ok(()));
+        assert_eq!(content.check_height(&headline(3, &["cpu 12%"])), Ok(()));
+        assert_eq!(content.check_height(&headline(3, &["cpu 12%", "mem 40%"])), Err((36, 32)));
+        // lines 8 pixels apart leave room for two detail lines under the headline
+        let content = Content::new(TextLayout{ lines: 4, interval: 8, ..layout() });
+        assert_eq!(content.check_height(&headline(4, &["cpu 12%", "mem 40%"])), Ok(()));
+        assert_eq!(content.check_height(&headline(4, &["cpu 12%", "mem 40%", "up 3d"])), Err((40, 32)));
+    }
+
+    #[test]
+    fn wrap_text_breaks_on_spaces() {
+        assert_eq!(wrap_text("the quick brown fox", 10), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap_text("  spaced   out  ", 20), vec!["spaced out"]);
+    }
+
+    #[test]
+    fn wrap_text_splits_words_longer_than_a_line() {
+        assert_eq!(wrap_text("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("ab cdefghij", 4), vec!["ab", "cdef", "ghij"]);
+        assert_eq!(wrap_text("héllo wörld", 3), vec!["hél", "lo", "wör", "ld"]);
+    }
+
+    #[test]
+    fn wrap_text_keeps_one_line() {
+        assert_eq!(wrap_text("", 10), vec![""]);
+        assert_eq!(wrap_text("word", 0), vec!["w", "o", "r", "d"]);
+    }
+
+    #[test]
+    fn wrap_text_by_follows_the_width_of_each_line() {
+        let widths = [3, 10, 5];
+        let lines = wrap_text_by("aaa bbb ccc ddd eee", |k| widths.get(k).copied().unwrap_or(5));
+        assert_eq!(lines, vec!["aaa", "bbb ccc", "ddd", "eee"]);
+    }
+
+    fn span(start: usize, end: usize, flags: &str) -> Span {
+        let attrs = Attrs {
+            invert: flags.contains('i'),
+            underline: flags.contains('u'),
+            blink: flags.contains('b'),
+        };
+        Span{ start, end, attrs }
+    }
+
+    #[test]
+    fn parse_markup_takes_spans_off_the_text() {
+        let (text, spans) = parse_markup("error {ib:42} at {u:disk}");
+        assert_eq!(text, "error 42 at disk");
+        assert_eq!(spans, vec![span(6, 8, "ib"), span(12, 16, "u")]);
+    }
+
+    #[test]
+    fn parse_markup_counts_characters() {
+        let (text, spans) = parse_markup("ü {i:é}");
+        assert_eq!(text, "ü é");
+        assert_eq!(spans, vec![span(2, 3, "i")]);
+    }
+
+    #[test]
+    fn parse_markup_keeps_braces_that_are_no_span() {
+        assert_eq!(parse_markup("{i:open"), (String::from("{i:open"), vec![]));
+        assert_eq!(parse_markup("{x:y}"), (String::from("{x:y}"), vec![]));
+        assert_eq!(parse_markup("{:y}"), (String::from("{:y}"), vec![]));
+        assert_eq!(parse_markup("a } b {"), (String::from("a } b {"), vec![]));
+        let (text, spans) = parse_markup("{{i:x}");
+        assert_eq!(text, "{x");
+        assert_eq!(spans, vec![span(1, 2, "i")]);
+    }
+
+    #[test]
+    fn bar_fraction_over_negative_and_wide_ranges() {
+        assert_eq!(Bar{ value: -5, min: -20, max: 40 }.fraction(), 0.25);
+        assert_eq!(Bar{ value: -30, min: -20, max: 40 }.fraction(), 0.0);
+        assert_eq!(Bar{ value: i32::MAX, min: i32::MIN, max: i32::MAX }.fraction(), 1.0);
+        assert_eq!(Bar{ value: 0, min: 5, max: 5 }.fraction(), 0.0);
+    }
 }
@@ -1,52 +1,61 @@
 use ssd1306::displaysize::DisplaySize;
 use ssd1306::prelude::WriteOnlyDataCommand;
 use ssd1306::mode::GraphicsMode;
+use ssd1306::mode::displaymode::DisplayModeTrait;
+use embedded_hal::digital::v2::OutputPin;
+use linux_embedded_hal::Delay;
 use embedded_graphics::prelude::*;
 use embedded_graphics::drawable::Drawable;
-use embedded_graphics::style::TextStyle;
+use embedded_graphics::style::TextStyleBuilder;
+use embedded_graphics::style::PrimitiveStyle;
+use embedded_graphics::primitives::Line;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::fonts;
 use embedded_graphics::fonts::Text;
 use embedded_graphics::fonts::Font;
+use embedded_graphics::image::Image;
+use embedded_graphics::image::ImageRaw;
 use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_graphics::geometry::Point;
 use async_std::io as aio;
 use crate::manager::Canvas;
 use crate::contents::Page;
+use crate::contents::TextFont;
+use crate::contents;
+use crate::contents::Bar;
+use crate::contents::Chart;
+use crate::contents::ChartKind;
 
-pub struct SSD1306Display<DI: WriteOnlyDataCommand, DSIZE: DisplaySize, F: Font> {
+pub struct SSD1306Display<DI: WriteOnlyDataCommand, DSIZE: DisplaySize> {
     display: GraphicsMode<DI, DSIZE>,
-    style: TextStyle<BinaryColor, F>,
+    font: TextFont,
     line_interval: i32,
+    invert: bool,
+    reset: Option<Box<ResetFn<DI, DSIZE>>>,
 }
 
-impl<DI: WriteOnlyDataCommand, DSIZE: DisplaySize, F: Font + Copy> Canvas for SSD1306Display<DI, DSIZE, F> {
+type ResetFn<DI, DSIZE> = dyn FnMut(&mut GraphicsMode<DI, DSIZE>) -> aio::Result<()>;
+
+impl<DI: WriteOnlyDataCommand, DSIZE: DisplaySize> Canvas for SSD1306Display<DI, DSIZE> {
     
     fn draw(&mut self, page: &Page) -> aio::Result<()> {
-        match page {
-            Page::Empty => {},
-            Page::Text{ lines } => {
-                self.display.clear();
-                let mut point = Point::zero();
-                for line in lines {
-                    Text::new(line.as_str(), point)
-                        .into_styled(self.style)
-                        .draw(&mut self.display)
-                        .map_err(|e| aio::Error::new(aio::ErrorKind::Other, format!("{:?}", e)))?;
-                    point.y += self.line_interval;
-                }
-            },
-            Page::BImage{ data, w, h } => {
+        draw_page(&mut self.display, page, self.font, self.line_interval, self.invert)
+            .map_err(|e| aio::Error::other(format!("{:?}", e)))
+    }
 
-            }
-        }
-        Ok(())
+    fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
     }
 
     fn init(&mut self) -> aio::Result<()> {
-        self.display.init().map_err(|e| aio::Error::new(aio::ErrorKind::Other, format!("{:?}", e)))
+        if let Some(reset) = &mut self.reset {
+            reset(&mut self.display)?;
+        }
+        self.display.init().map_err(|e| aio::Error::other(format!("{:?}", e)))
     }
 
     fn flush(&mut self) -> aio::Result<()> {
-        self.display.flush().map_err(|e| aio::Error::new(aio::ErrorKind::Other, format!("{:?}", e)))
+        self.display.flush().map_err(|e| aio::Error::other(format!("{:?}", e)))
     }
 
     fn clear(&mut self) -> aio::Result<()> {
@@ -55,13 +64,272 @@ impl<DI: WriteOnlyDataCommand, DSIZE: DisplaySize, F: Font + Copy> Canvas for SS
     }
 }
 
-impl<DI: WriteOnlyDataCommand, DSIZE: DisplaySize, F: Font> SSD1306Display<DI, DSIZE, F> {
+impl<DI: WriteOnlyDataCommand, DSIZE: DisplaySize> SSD1306Display<DI, DSIZE> {
     
-    pub fn new(display: GraphicsMode<DI, DSIZE>, style: TextStyle<BinaryColor, F>, line_interval: u32) -> Self {
+    /**
+     * `font` is used for the lines that do not choose their own
+     */
+    pub fn new(display: GraphicsMode<DI, DSIZE>, font: TextFont, line_interval: u32) -> Self {
         SSD1306Display {
             display,
-            style,
+            font,
             line_interval: line_interval as i32,
+            invert: false,
+            reset: None,
+        }
+    }
+
+    /**
+     * pulse `rst` before every `init`; the pin is kept so the line stays driven high afterwards
+     */
+    pub fn with_reset<RST>(self, rst: RST) -> Self
+    where
+        RST: OutputPin + 'static,
+        RST::Error: std::fmt::Debug
+    {
+        let mut rst = rst;
+        let reset = move |display: &mut GraphicsMode<DI, DSIZE>| {
+            display.reset(&mut rst, &mut Delay)
+                .map_err(|e| aio::Error::other(format!("{:?}", e)))
+        };
+        SSD1306Display {
+            reset: Some(Box::new(reset)),
+            ..self
+        }
+    }
+}
+
+/**
+ * render `page` onto any monochrome target, shared by the panel and the hardware-free canvases;
+ * text lines without a font of their own use `font`, with `invert` lit and dark pixels are swapped
+ */
+pub fn draw_page<D>(target: &mut D, page: &Page, font: TextFont, line_interval: i32, invert: bool) -> Result<(), D::Error>
+where
+    D: DrawTarget<BinaryColor>
+{
+    if invert {
+        render(&mut Inverted(target), page, font, line_interval)
+    } else {
+        render(target, page, font, line_interval)
+    }
+}
+
+/**
+ * `line_interval` is meant for `font`, the gap it leaves below a line is kept under taller and shorter lines;
+ * lines are aligned by the character width of their font, then their inverted and underlined spans drawn over them;
+ * a line with a bar is its label followed by the bar
+ */
+fn render<D>(target: &mut D, page: &Page, font: TextFont, line_interval: i32) -> Result<(), D::Error>
+where
+    D: DrawTarget<BinaryColor>
+{
+    match page {
+        Page::Empty => {},
+        Page::Text{ lines, .. } => {
+            target.clear(BinaryColor::Off)?;
+            let gap = line_interval - font.size().1 as i32;
+            let width = target.size().width;
+            let mut y = 0;
+            for (k, line) in lines.iter().enumerate() {
+                let style = page.style(k);
+                let line_font = style.font.unwrap_or(font);
+                let (char_width, char_height) = line_font.size();
+                let len = line.chars().count() as u32 * char_width;
+                // a bar takes the rest of the line, its label stays at the start
+                let x = if style.bar.is_some() { 0 } else { style.align.offset(len, width) as i32 };
+                draw_text(target, line, Point::new(x, y), line_font, false)?;
+                if let Some(bar) = &style.bar {
+                    draw_bar(target, bar, Point::new(len as i32, y), width as i32, line_font)?;
+                }
+                for span in &style.spans {
+                    let start = Point::new(x + (span.start * char_width as usize) as i32, y);
+                    if span.attrs.invert {
+                        let text: String = line.chars().skip(span.start).take(span.end - span.start).collect();
+                        draw_text(target, &text, start, line_font, true)?;
+                    }
+                    if span.attrs.underline && span.end > span.start {
+                        let bottom = start.y + char_height as i32 - 1;
+                        let end = start.x + ((span.end - span.start) * char_width as usize) as i32 - 1;
+                        let color = if span.attrs.invert { BinaryColor::Off } else { BinaryColor::On };
+                        Line::new(Point::new(start.x, bottom), Point::new(end, bottom))
+                            .into_styled(PrimitiveStyle::with_stroke(color, 1))
+                            .draw(target)?;
+                    }
+                }
+                y += char_height as i32 + gap;
+            }
+        },
+        Page::BImage{ data, w, h } => {
+            target.clear(BinaryColor::Off)?;
+            let size = target.size();
+            let (dw, dh) = (size.width as i32, size.height as i32);
+            let offset = Point::new((dw - *w as i32) / 2, (dh - *h as i32) / 2);
+            let raw = ImageRaw::<BinaryColor>::new(data, *w, *h);
+            let image = Image::new(&raw, offset);
+            let pixels = image.into_iter()
+                .filter(|Pixel(p, _)| p.x >= 0 && p.x < dw && p.y >= 0 && p.y < dh);
+            target.draw_iter(pixels)?;
+        },
+        Page::Chart(chart) => {
+            target.clear(BinaryColor::Off)?;
+            draw_chart(target, chart, font, line_interval)?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * the title line with the newest sample at its end, then the samples scaled between the lowest and highest one,
+ * which are written next to the axis; the newest sample is at the right edge
+ */
+fn draw_chart<D>(target: &mut D, chart: &Chart, font: TextFont, line_interval: i32) -> Result<(), D::Error>
+where
+    D: DrawTarget<BinaryColor>
+{
+    let size = target.size();
+    let (w, h) = (size.width as i32, size.height as i32);
+    let (char_width, char_height) = font.size();
+    let (char_width, char_height) = (char_width as i32, char_height as i32);
+    let last = chart.samples.back().copied();
+    let mut top = 0;
+    if let Some(title) = &chart.title {
+        draw_text(target, title, Point::zero(), font, false)?;
+        if let Some(last) = last {
+            let value = contents::format_sample(last);
+            draw_text(target, &value, Point::new(w - value.len() as i32 * char_width, 0), font, false)?;
         }
+        top = line_interval;
+    }
+    let (lo, hi) = match chart.range() {
+        Some(range) => range,
+        None => return Ok(())
+    };
+    let bottom = h - 1;
+    let (hi_label, lo_label) = (contents::format_sample(hi), contents::format_sample(lo));
+    let axis = std::cmp::max(hi_label.len(), lo_label.len()) as i32 * char_width + 1;
+    draw_text(target, &hi_label, Point::new(0, top), font, false)?;
+    if bottom - top + 1 >= 2 * char_height {
+        draw_text(target, &lo_label, Point::new(0, bottom - char_height + 1), font, false)?;
     }
-}
\ No newline at end of file
+    let stroke = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    Line::new(Point::new(axis, top), Point::new(axis, bottom)).into_styled(stroke).draw(target)?;
+    Line::new(Point::new(axis, bottom), Point::new(w - 1, bottom)).into_styled(stroke).draw(target)?;
+
+    // one column per sample, wider when fewer samples are kept than there are columns
+    let (left, right) = (axis + 1, w - 1);
+    let step = std::cmp::max(1, (right - left + 1) / std::cmp::max(chart.capacity, 1) as i32);
+    let shown = std::cmp::min(chart.samples.len(), ((right - left) / step + 1) as usize);
+    let height = (bottom - 1 - top) as f32;
+    let points = chart.samples.iter()
+        .skip(chart.samples.len() - shown)
+        .enumerate()
+        .map(|(k, value)| {
+            let x = right - (shown - 1 - k) as i32 * step;
+            let y = bottom - 1 - ((value - lo) / (hi - lo) * height).round() as i32;
+            Point::new(x, y)
+        });
+    match chart.kind {
+        ChartKind::Line => {
+            let mut previous: Option<Point> = None;
+            for point in points {
+                let from = previous.unwrap_or(point);
+                Line::new(from, point).into_styled(stroke).draw(target)?;
+                previous = Some(point);
+            }
+        }
+        ChartKind::Bars => {
+            // bars more than a column wide leave a gap to the next one
+            let width = if step > 1 { step - 1 } else { 1 };
+            for point in points {
+                let x = std::cmp::max(point.x - width + 1, left);
+                Rectangle::new(Point::new(x, point.y), Point::new(point.x, bottom - 1))
+                    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                    .draw(target)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * `bar` between `start` and the right edge at `width`: an outline filled up to the value,
+ * with the value written after it; left out where there is no room for it
+ */
+fn draw_bar<D>(target: &mut D, bar: &Bar, start: Point, width: i32, font: TextFont) -> Result<(), D::Error>
+where
+    D: DrawTarget<BinaryColor>
+{
+    let (char_width, char_height) = font.size();
+    let (char_width, char_height) = (char_width as i32, char_height as i32);
+    let value = bar.value.to_string();
+    let value_x = width - value.len() as i32 * char_width;
+    draw_text(target, &value, Point::new(value_x, start.y), font, false)?;
+    let left = if start.x > 0 { start.x + char_width } else { 0 };
+    let right = value_x - char_width - 1;
+    if right - left < 2 {
+        return Ok(());
+    }
+    let (top, bottom) = (start.y + 1, start.y + char_height - 2);
+    Rectangle::new(Point::new(left, top), Point::new(right, bottom))
+        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        .draw(target)?;
+    let filled = ((right - left - 1) as f32 * bar.fraction()) as i32;
+    if filled > 0 {
+        Rectangle::new(Point::new(left + 1, top + 1), Point::new(left + filled, bottom - 1))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(target)?;
+    }
+    Ok(())
+}
+
+/**
+ * with `invert` the text is dark on a lit background
+ */
+fn draw_text<D>(target: &mut D, text: &str, point: Point, font: TextFont, invert: bool) -> Result<(), D::Error>
+where
+    D: DrawTarget<BinaryColor>
+{
+    match font {
+        TextFont::Font6x8 => draw_styled(target, text, point, fonts::Font6x8, invert),
+        TextFont::Font6x12 => draw_styled(target, text, point, fonts::Font6x12, invert),
+        TextFont::Font8x16 => draw_styled(target, text, point, fonts::Font8x16, invert),
+        TextFont::Font12x16 => draw_styled(target, text, point, fonts::Font12x16, invert),
+    }
+}
+
+fn draw_styled<D, F>(target: &mut D, text: &str, point: Point, font: F, invert: bool) -> Result<(), D::Error>
+where
+    D: DrawTarget<BinaryColor>,
+    F: Font + Copy
+{
+    let (fg, bg) = if invert { (BinaryColor::Off, BinaryColor::On) } else { (BinaryColor::On, BinaryColor::Off) };
+    let style = TextStyleBuilder::new(font)
+        .text_color(fg)
+        .background_color(bg)
+        .build();
+    Text::new(text, point)
+        .into_styled(style)
+        .draw(target)
+}
+
+/**
+ * passes everything drawn on to the wrapped target with the colors swapped
+ */
+struct Inverted<'a, D>(&'a mut D);
+
+impl<'a, D: DrawTarget<BinaryColor>> DrawTarget<BinaryColor> for Inverted<'a, D> {
+    type Error = D::Error;
+
+    fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> Result<(), Self::Error> {
+        let Pixel(p, color) = pixel;
+        self.0.draw_pixel(Pixel(p, color.invert()))
+    }
+
+    fn size(&self) -> Size {
+        self.0.size()
+    }
+
+    fn clear(&mut self, color: BinaryColor) -> Result<(), Self::Error> {
+        self.0.clear(color.invert())
+    }
+}
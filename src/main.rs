@@ -1,69 +1,95 @@
 mod contents;
+mod config;
 mod operation;
 mod server;
 mod manager;
 mod display;
+mod simulator;
+mod terminal;
 
 use std::time::Duration;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
 use async_std::task;
 use async_std::io as aio;
+use async_std::channel;
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
 use linux_embedded_hal::I2cdev;
+use linux_embedded_hal::Spidev;
+use linux_embedded_hal::CdevPin;
+use linux_embedded_hal::spidev::SpidevOptions;
+use linux_embedded_hal::spidev::SpiModeFlags;
+use linux_embedded_hal::gpio_cdev::Chip;
+use linux_embedded_hal::gpio_cdev::LineRequestFlags;
 use ssd1306::builder::I2CDIBuilder;
 use ssd1306::displaysize;
 use ssd1306::Builder;
-use embedded_graphics::fonts;
-use embedded_graphics::pixelcolor::BinaryColor;
-use embedded_graphics::style::TextStyleBuilder;
+use ssd1306::prelude::SPIInterfaceNoCS;
 use server::Server;
+use simulator::SimulatorDisplay;
+use simulator::SnapshotFormat;
+use terminal::TerminalDisplay;
 use manager::Canvas;
 use manager::Manager;
+use manager::Setup;
 use contents::Page;
 use contents::Content;
+use contents::TextLayout;
+use config::Config;
+use config::parse_display_size;
 use clap::App;
 use clap::Arg;
+use clap::ArgMatches;
 
 
 macro_rules! build0 {
-    ($config:ident, $interface:ident, $size:expr, [$($font:expr=>$fontN:expr),+]) => {
+    ($config:ident, $interface:ident, $reset:ident, ($($size:expr=>$sizeN:expr),+)) => {
         {
-            let display = Builder::new().size($size).connect($interface).into();
-            match $config.font {
+            match $config.display_size.as_str() {
             $(
-                $font => {
-                    let text_style = TextStyleBuilder::new($fontN)
-                        .text_color(BinaryColor::On)
-                        .background_color(BinaryColor::Off)
-                        .build();
-                    let canvas = display::SSD1306Display::new(display, text_style, $config.line_interval);
-                    Box::new(canvas)
+                $size => {
+                    build0!($config, $interface, $reset, $sizeN)
                 }
             )+
                 _ => {
-                    return Err(aio::Error::new(aio::ErrorKind::Other, format!("unsupport font:{}", $config.font)))
+                    return Err(aio::Error::other(format!("unsupport display-size:{}", $config.display_size)))
                 }
             }
         }
     };
-    ($config:ident, $interface:ident, ($($size:expr=>$sizeN:expr),+), [$($font:expr=>$fontN:expr),+]) => {
+    ($config:ident, $interface:ident, $reset:ident, $size:expr) => {
         {
-            match $config.display_size {
-            $(
-                $size => {
-                    build0!($config, $interface, $sizeN, [$($font=>$fontN),+])
-                }
-            )+
-                _ => {
-                    return Err(aio::Error::new(aio::ErrorKind::Other, format!("unsupport display-size:{}", $config.display_size)))
-                }
-            }
+            let display = Builder::new().size($size).connect($interface).into();
+            let canvas = display::SSD1306Display::new(display, $config.text_layout().font, $config.line_interval);
+            let canvas: Box<dyn Canvas> = match $reset {
+                Some(rst) => Box::new(canvas.with_reset(rst)),
+                None => Box::new(canvas)
+            };
+            canvas
         }
-    }
+    };
+    ($config:ident, $interface:ident, $reset:ident) => {
+        build0!(
+            $config,
+            $interface,
+            $reset,
+            (
+                "128x32"=>displaysize::DisplaySize128x32,
+                "128x64"=>displaysize::DisplaySize128x64,
+                "96x16"=>displaysize::DisplaySize96x16
+            )
+        )
+    };
 }
 
-const NAME: &'static str = env!("CARGO_PKG_NAME");
-const DESCRIPTION: &'static str = env!("CARGO_PKG_DESCRIPTION");
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-const AUTHORS: &'static str = env!("CARGO_PKG_AUTHORS");
+const NAME: &str = env!("CARGO_PKG_NAME");
+const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+
+const SPI_SPEED_HZ: u32 = 8_000_000;
 
 fn main() {
 
@@ -71,6 +97,18 @@ fn main() {
         .about(DESCRIPTION)
         .version(VERSION)
         .author(AUTHORS)
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .help("toml file with the settings below and the initial `pages`, overridden by the command line")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("state")
+                .long("state")
+                .help("json file the pages are saved to after every change and restored from at startup")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("size")
                 .short("s")
@@ -82,7 +120,7 @@ fn main() {
             Arg::with_name("font")
                 .short("f")
                 .long("font")
-                .help("font, can be `6x8`, `6x12`, `8x12`, `8x16`")
+                .help("font, can be `6x8`, `6x12`, `8x16`, `12x16`")
                 .takes_value(true)
         )
         .arg(
@@ -99,63 +137,256 @@ fn main() {
                 .help("time for each page to stay, in millisecond")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("marquee_step")
+                .long("marquee_step")
+                .help("time between the one character steps of a scrolling line, in millisecond")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("marquee_pause")
+                .long("marquee_pause")
+                .help("time a scrolling line stays at its start and end, in millisecond")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("scroll_step")
+                .long("scroll_step")
+                .help("time between the one line steps of a long page, in millisecond")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("scroll_pause")
+                .long("scroll_pause")
+                .help("time a long page stays at its top and bottom, in millisecond")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("canvas")
+                .short("c")
+                .long("canvas")
+                .help("where to draw, can be `ssd1306`, `simulator`, `terminal`, `print`")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("snapshot")
+                .long("snapshot")
+                .help("file written by the simulator canvas on every refresh, `.png` or `.pbm`")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("interface")
+                .short("i")
+                .long("interface")
+                .help("wiring of the ssd1306 canvas, can be `i2c`, `spi`")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("i2c_bus")
+                .long("i2c_bus")
+                .help("i2c device of the ssd1306 canvas, like `/dev/i2c-1`")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("i2c_address")
+                .long("i2c_address")
+                .help("i2c address of the ssd1306 canvas, like `0x3C` or `0x3D`")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("spi_bus")
+                .long("spi_bus")
+                .help("spi device of the ssd1306 canvas, like `/dev/spidev0.0`")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("gpio_chip")
+                .long("gpio_chip")
+                .help("gpio character device holding the spi control lines, like `/dev/gpiochip0`")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("spi_dc")
+                .long("spi_dc")
+                .help("gpio line connected to D/C of the spi display")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("spi_reset")
+                .long("spi_reset")
+                .help("gpio line connected to RES of the spi display, `none` if not wired")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("bind")
-                .required(true)
+                .help("address to listen on, like `127.0.0.1:17900`")
                 .index(1)
         )
         .get_matches();
 
-    let config = Config {
-        bind: matches.value_of("bind").expect("bind"),
-        page_roll_interval: matches.value_of("page_roll_interval").expect("page_roll_interval").parse().unwrap(),
-        display_size: matches.value_of("size").expect("size"),
-        font: matches.value_of("font").expect("font"),
-        line_interval: matches.value_of("line_interval").expect("line_interval").parse().unwrap(),
+    let config = match Config::load(&matches) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
     };
-    task::block_on(server(config)).unwrap();
+    if let Err(e) = task::block_on(server(config, matches)) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
 
-async fn server<'a>(config: Config<'a>) -> aio::Result<()> {
-    
-    let i2c = I2cdev::new("/dev/i2c-1").unwrap();
-    let interface = I2CDIBuilder::new().init(i2c);
-
-    let canvas: Box<dyn Canvas> = match config.display_size {
-        "128x32" => {
-            build0!(
-                config, 
-                interface, 
-                displaysize::DisplaySize128x32,
-                ["6x8"=>fonts::Font6x8, "6x12"=>fonts::Font6x12, "8x16"=>fonts::Font8x16, "12x16"=>fonts::Font12x16]
-            )
+async fn server(config: Config, matches: ArgMatches<'static>) -> aio::Result<()> {
+
+    let layout = config.text_layout();
+    let mut content = Content::new(layout);
+    for (i, page) in config.pages.iter().enumerate() {
+        content.set(i, page.to_page(layout.lines, layout.columns(None))?);
+        content.set_name(i, page.name.clone());
+        content.set_dwell(i, page.dwell.filter(|ms| *ms > 0));
+        content.set_wrap(i, page.wrap);
+        content.set_marquee(i, page.marquee);
+        content.set_length(i, page.length);
+    }
+    let bind = config.bind.clone();
+    let state = config.state.as_ref().map(PathBuf::from);
+    let settings = Settings {
+        matches,
+        config,
+        previous: None,
+    };
+    let mgr = Manager::new(content, Box::new(settings), state)?;
+    let server = Server::new(mgr, operation::MAX_LINE, 4);
+    server.start_server(bind.as_str(), reload_signals()?).await?;
+    Ok(())
+}
+
+/**
+ * SIGHUP is caught on a separate thread and forwarded to the server loop
+ */
+fn reload_signals() -> aio::Result<channel::Receiver<()>> {
+    let mut signals = Signals::new([SIGHUP])?;
+    let (sender, receiver) = channel::unbounded();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            if sender.try_send(()).is_err() {
+                break;
+            }
         }
-        "128x64" => {
-            build0!(
-                config,
-                interface, 
-                displaysize::DisplaySize128x64,
-                ["6x8"=>fonts::Font6x8, "6x12"=>fonts::Font6x12, "8x16"=>fonts::Font8x16, "12x16"=>fonts::Font12x16]
-            )
+    });
+    Ok(receiver)
+}
+
+/**
+ * the settings in use, re-read from the same `--config` file and command line on reload
+ */
+struct Settings {
+    matches: ArgMatches<'static>,
+    config: Config,
+    previous: Option<Config>,
+}
+
+impl Setup for Settings {
+
+    fn reload(&mut self) -> aio::Result<()> {
+        let config = Config::load(&self.matches)?;
+        self.previous = Some(std::mem::replace(&mut self.config, config));
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+        if let Some(config) = self.previous.take() {
+            self.config = config;
         }
-        "96x16" => {
-            build0!(
-                config, 
-                interface, 
-                displaysize::DisplaySize96x16,
-                ["6x8"=>fonts::Font6x8, "6x12"=>fonts::Font6x12, "8x16"=>fonts::Font8x16, "12x16"=>fonts::Font12x16]
-            )
+    }
+
+    fn build_canvas(&self) -> aio::Result<Box<dyn Canvas>> {
+        build_canvas(&self.config)
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.config.page_roll_interval as u64)
+    }
+
+    fn text_layout(&self) -> TextLayout {
+        self.config.text_layout()
+    }
+
+    fn marquee(&self) -> (Duration, Duration) {
+        let ms = |ms: u32| Duration::from_millis(ms as u64);
+        (ms(self.config.marquee_step), ms(self.config.marquee_pause))
+    }
+
+    fn scroll(&self) -> (Duration, Duration) {
+        let ms = |ms: u32| Duration::from_millis(ms as u64);
+        (ms(self.config.scroll_step), ms(self.config.scroll_pause))
+    }
+}
+
+fn build_canvas(config: &Config) -> aio::Result<Box<dyn Canvas>> {
+    let canvas = match config.canvas.as_str() {
+        "ssd1306" => {
+            match config.interface.as_str() {
+                "i2c" => {
+                    let i2c = I2cdev::new(&config.i2c_bus)
+                        .map_err(|e| aio::Error::other(format!("cannot open i2c bus {}: {}", config.i2c_bus, e)))?;
+                    let interface = I2CDIBuilder::new().with_i2c_addr(config.i2c_address).init(i2c);
+                    let reset: Option<CdevPin> = None;
+                    build0!(config, interface, reset)
+                }
+                "spi" => {
+                    let mut spi = Spidev::open(&config.spi_bus)
+                        .map_err(|e| aio::Error::other(format!("cannot open spi bus {}: {}", config.spi_bus, e)))?;
+                    let options = SpidevOptions::new()
+                        .max_speed_hz(SPI_SPEED_HZ)
+                        .mode(SpiModeFlags::SPI_MODE_0)
+                        .build();
+                    spi.0.configure(&options)
+                        .map_err(|e| aio::Error::other(format!("cannot configure spi bus {}: {}", config.spi_bus, e)))?;
+                    let mut chip = Chip::new(&config.gpio_chip)
+                        .map_err(|e| aio::Error::other(format!("cannot open gpio chip {}: {}", config.gpio_chip, e)))?;
+                    let dc = request_output(&mut chip, config.spi_dc, "ssd1306-dc")?;
+                    let reset = match config.spi_reset {
+                        Some(line) => Some(request_output(&mut chip, line, "ssd1306-reset")?),
+                        None => None
+                    };
+                    let interface = SPIInterfaceNoCS::new(spi, dc);
+                    build0!(config, interface, reset)
+                }
+                _ => {
+                    return Err(aio::Error::other(format!("unsupport interface:{}", config.interface)))
+                }
+            }
+        }
+        "simulator" => {
+            let (width, height) = parse_display_size(&config.display_size)
+                .ok_or_else(|| aio::Error::other(format!("unsupport display-size:{}", config.display_size)))?;
+            let path = PathBuf::from(&config.snapshot);
+            let format = SnapshotFormat::from_path(&path)
+                .ok_or_else(|| aio::Error::other(format!("unsupport snapshot format:{}", config.snapshot)))?;
+            Box::new(SimulatorDisplay::new(width, height, config.text_layout().font, config.line_interval, path, format))
+        }
+        "terminal" => {
+            let (width, height) = parse_display_size(&config.display_size)
+                .ok_or_else(|| aio::Error::other(format!("unsupport display-size:{}", config.display_size)))?;
+            Box::new(TerminalDisplay::new(width, height, config.text_layout().font, config.line_interval))
+        }
+        "print" => {
+            Box::new(Print)
         }
         _ => {
-            return Err(aio::Error::new(aio::ErrorKind::Other, format!("unsupport display-size:{}", config.display_size)))
+            return Err(aio::Error::other(format!("unsupport canvas:{}", config.canvas)))
         }
     };
+    Ok(canvas)
+}
 
-
-    let mgr = Manager::new(Content::new(4), canvas)?;
-    let server = Server::new(mgr, Duration::from_millis(config.page_roll_interval as u64), 1024, 4);
-    server.start_server(config.bind).await?;
-    Ok(())
+fn request_output(chip: &mut Chip, line: u32, consumer: &str) -> aio::Result<CdevPin> {
+    chip.get_line(line)
+        .and_then(|line| line.request(LineRequestFlags::OUTPUT, 1, consumer))
+        .and_then(CdevPin::new)
+        .map_err(|e| aio::Error::other(format!("cannot request gpio line {}: {}", line, e)))
 }
 
 pub struct Print;
@@ -167,6 +398,9 @@ impl Canvas for Print {
         Ok(())
     }
 
+    fn set_invert(&mut self, _invert: bool) {
+    }
+
     fn init(&mut self) -> aio::Result<()> {
         Ok(())
     }
@@ -179,24 +413,3 @@ impl Canvas for Print {
         Ok(())
     }
 }
-
-pub struct Config<'a> {
-    bind: &'a str,
-    page_roll_interval: u32,
-    display_size: &'a str,
-    font: &'a str,
-    line_interval: u32
-}
-
-impl<'a> Default for Config<'a> {
-
-    fn default() -> Self {
-        Config {
-            bind: "127.0.0.1:17900",
-            page_roll_interval: 5000,
-            display_size: "128x32",
-            font: "6x8",
-            line_interval: 10
-        }
-    }
-}
\ No newline at end of file
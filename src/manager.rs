@@ -1,11 +1,20 @@
+use std::fs;
 use std::fmt::Write as _;
 use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+use std::path::PathBuf;
 use std::cell::RefCell;
 use async_std::io as aio;
 use crate::operation::CommandSplit;
 use crate::operation::Operation;
+use crate::operation::OperationError;
+use crate::operation::ParseError;
+use crate::contents;
 use crate::contents::Content;
 use crate::contents::Page;
+use crate::contents::LineStyle;
+use crate::contents::TextLayout;
 use crate::server::Handler;
 use crate::server::Buf;
 
@@ -19,8 +28,40 @@ pub trait Canvas {
     fn flush(&mut self) -> aio::Result<()>;
 
     fn clear(&mut self) -> aio::Result<()>;
+
+    /// swap lit and dark pixels in everything drawn from now on
+    fn set_invert(&mut self, invert: bool);
+}
+
+/**
+ * where the canvas and the roll interval come from, so they can be rebuilt at runtime
+ */
+pub trait Setup {
+
+    /// re-read the settings, keeping the current ones if the new ones are invalid
+    fn reload(&mut self) -> aio::Result<()>;
+
+    /// go back to the settings used before the last successful `reload`
+    fn rollback(&mut self);
+
+    fn build_canvas(&self) -> aio::Result<Box<dyn Canvas>>;
+
+    fn interval(&self) -> Duration;
+
+    /// lines per page, line width and default font
+    fn text_layout(&self) -> TextLayout;
+
+    /// time between the steps of a scrolling line and the pause at its start and end
+    fn marquee(&self) -> (Duration, Duration);
+
+    /// time between the steps of a scrolling long page and the pause at its top and bottom
+    fn scroll(&self) -> (Duration, Duration);
 }
 
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+/// how long the schedule sleeps when nothing is due, e.g. under an alert waiting to be acknowledged
+const IDLE_SCHEDULE: Duration = Duration::from_secs(3600);
+
 pub struct Manager {
     inner: Rc<RefCell<ManagerInner>>
 }
@@ -28,26 +69,524 @@ pub struct Manager {
 struct ManagerInner {
     content: Content,
     canvas: Box<dyn Canvas>,
+    setup: Box<dyn Setup>,
+    state: Option<PathBuf>,
     index: usize,
+    current: Option<usize>,
+    due: Instant,
+    alert: Option<Alert>,
+    scroll: Vec<Scroll>,
+    shown: Instant,
+    top: usize,
+    /// blinking spans of the current page are hidden
+    blinked: bool,
+}
+
+/**
+ * where a line that is too long for the display currently starts, and when it moves on
+ */
+struct Scroll {
+    offset: usize,
+    next: Instant,
+}
+
+/**
+ * shown instead of the rotation until `until` (or until acknowledged if `None`)
+ */
+struct Alert {
+    page: Page,
+    until: Option<Instant>,
+    invert: bool,
+    blink: bool,
+    blinked: bool,
+    next_blink: Instant,
 }
 
 impl Manager {
     
-    pub fn new(content: Content, canvas: Box<dyn Canvas>) -> aio::Result<Self> {
-        let mut canvas = canvas;
-        canvas.init()?;
-        canvas.flush()?;
-        let inner = ManagerInner {
+    /**
+     * with a `state` file the pages saved there replace the initial `content`,
+     * and it is rewritten after every change
+     */
+    pub fn new(content: Content, setup: Box<dyn Setup>, state: Option<PathBuf>) -> aio::Result<Self> {
+        let mut content = content;
+        if let Some(path) = &state {
+            match content.load(path) {
+                // a damaged state must not keep the display from coming up, it is kept aside to look at
+                Err(e) if e.kind() == aio::ErrorKind::InvalidData => {
+                    let aside = path.with_extension("bad");
+                    eprintln!("{}, starting from the configured pages", e);
+                    match fs::rename(path, &aside) {
+                        Ok(()) => eprintln!("damaged state moved to {}", aside.display()),
+                        Err(e) => eprintln!("cannot move damaged state to {}: {}", aside.display(), e),
+                    }
+                }
+                loaded => {
+                    loaded?;
+                }
+            }
+        }
+        content.expire(Instant::now());
+        let canvas = setup.build_canvas()?;
+        let mut inner = ManagerInner {
             content,
-            canvas,
+            canvas: Box::new(Detached),
+            setup,
+            state,
             index: 0,
+            current: None,
+            due: Instant::now(),
+            alert: None,
+            scroll: Vec::new(),
+            shown: Instant::now(),
+            top: 0,
+            blinked: false,
         };
+        // show the first restored page right away instead of waiting for the schedule
+        let n = inner.content.len();
+        if n > 0 {
+            inner.set_current(Some(0));
+            inner.index = 1 % n;
+            inner.due += inner.dwell(0);
+        }
+        inner.attach(canvas)?;
         Ok(Manager {
             inner: Rc::new(RefCell::new(inner))
         })
     }
 }
 
+impl ManagerInner {
+
+    fn attach(&mut self, canvas: Box<dyn Canvas>) -> aio::Result<()> {
+        let mut canvas = canvas;
+        canvas.init()?;
+        canvas.flush()?;
+        self.canvas = canvas;
+        self.show()
+    }
+
+    /**
+     * draw the alert if there is one, the current page otherwise
+     */
+    fn show(&mut self) -> aio::Result<()> {
+        self.fit_scroll();
+        if let Some(alert) = &self.alert {
+            self.canvas.set_invert(alert.invert != alert.blinked);
+            self.canvas.draw(&alert.page)?;
+        } else {
+            self.canvas.set_invert(false);
+            match self.view() {
+                Some(page) => self.canvas.draw(&page)?,
+                None => self.canvas.clear()?,
+            }
+        }
+        self.canvas.flush()
+    }
+
+    /**
+     * switch to page `current`, scrolling lines and long pages start over when it is another page
+     */
+    fn set_current(&mut self, current: Option<usize>) {
+        if self.current != current {
+            let now = Instant::now();
+            let (_, pause) = self.setup.marquee();
+            let next = now + pause;
+            let n = current.map_or(0, |i| self.content.page_lines(i));
+            self.scroll = (0 .. n).map(|_| Scroll{ offset: 0, next }).collect();
+            self.shown = now;
+            self.top = 0;
+            self.blinked = false;
+        }
+        self.current = current;
+    }
+
+    /**
+     * keep a scroll state for every line of the current page and its top on the page,
+     * the page may have changed its number of lines since it came up (`@N$lines=`, `@^`)
+     */
+    fn fit_scroll(&mut self) {
+        let n = self.current.map_or(0, |i| self.content.page_lines(i));
+        if self.scroll.len() != n {
+            let (_, pause) = self.setup.marquee();
+            let next = Instant::now() + pause;
+            self.scroll.resize_with(n, || Scroll{ offset: 0, next });
+        }
+        self.top = std::cmp::min(self.top, self.current.map_or(0, |i| self.extra_lines(i)));
+    }
+
+    /**
+     * the current page as it is to be drawn: the visible part of a long page,
+     * with scrolling lines cut to what fits and blinking spans blanked while they are off
+     */
+    fn view(&self) -> Option<Page> {
+        let i = self.current?;
+        let page = self.content.get(i)?;
+        match page {
+            Page::Text{ lines, .. } => {
+                let marquee = self.content.marquee(i);
+                let (lines, styles) = (self.top .. lines.len())
+                    .take(self.content.line_limit())
+                    .map(|k| {
+                        let (line, style) = (&lines[k], page.style(k));
+                        let (line, style) = if marquee {
+                            let offset = self.scroll.get(k).map_or(0, |s| s.offset);
+                            let width = self.content.line_width_at(i, k);
+                            (line.chars().skip(offset).take(width).collect(), style.window(offset, width))
+                        } else {
+                            (line.clone(), style)
+                        };
+                        if self.blinked {
+                            hide_blinking(&line, style)
+                        } else {
+                            (line, style)
+                        }
+                    })
+                    .unzip();
+                Some(Page::Text{ lines, styles })
+            }
+            _ => Some(page.clone())
+        }
+    }
+
+    /**
+     * whether a visible line of the current page has blinking spans
+     */
+    fn has_blink(&self) -> bool {
+        match self.current.and_then(|i| self.content.get(i)) {
+            Some(page) => (self.top .. page.line_num())
+                .take(self.content.line_limit())
+                .any(|k| page.style(k).has_blink()),
+            None => false
+        }
+    }
+
+    /**
+     * whether blinking spans are off at `now`, they go on and off every `BLINK_INTERVAL` from when the page came up
+     */
+    fn blink_phase(&self, now: Instant) -> bool {
+        self.has_blink() && (now.saturating_duration_since(self.shown).as_millis() / BLINK_INTERVAL.as_millis()) % 2 == 1
+    }
+
+    /**
+     * when the blinking spans of the current page go on or off next
+     */
+    fn next_text_blink(&self, now: Instant) -> Option<Instant> {
+        if !self.has_blink() {
+            return None;
+        }
+        let k = now.saturating_duration_since(self.shown).as_millis() / BLINK_INTERVAL.as_millis();
+        Some(self.shown + BLINK_INTERVAL * (k as u32 + 1))
+    }
+
+    /**
+     * lines of page `i` that do not fit on the display
+     */
+    fn extra_lines(&self, i: usize) -> usize {
+        self.content.get(i).map_or(0, |page| page.line_num().saturating_sub(self.content.line_limit()))
+    }
+
+    /**
+     * first visible line of the current page at `now`: a long page stays at the top for the pause,
+     * then moves one line per step until its last line is shown
+     */
+    fn top_line(&self, now: Instant) -> usize {
+        let extra = self.current.map_or(0, |i| self.extra_lines(i));
+        if extra == 0 {
+            return 0;
+        }
+        let (step, pause) = self.setup.scroll();
+        let elapsed = now.saturating_duration_since(self.shown);
+        if elapsed < pause {
+            0
+        } else {
+            let steps = (elapsed - pause).as_millis() / std::cmp::max(step.as_millis(), 1);
+            std::cmp::min(steps as usize + 1, extra)
+        }
+    }
+
+    /**
+     * when the current long page moves on by a line
+     */
+    fn next_top(&self, now: Instant) -> Option<Instant> {
+        let extra = self.extra_lines(self.current?);
+        let top = self.top_line(now);
+        if top >= extra {
+            None
+        } else {
+            let (step, pause) = self.setup.scroll();
+            Some(self.shown + pause + step * top as u32)
+        }
+    }
+
+    /**
+     * move long pages and scrolling lines on, redrawing if anything moved
+     */
+    fn animate(&mut self, now: Instant) -> aio::Result<()> {
+        let top = self.top_line(now);
+        let mut moved = top != self.top;
+        self.top = top;
+        moved |= self.step_marquee(now);
+        let blinked = self.blink_phase(now);
+        moved |= blinked != self.blinked;
+        self.blinked = blinked;
+        if moved {
+            self.show()?;
+        }
+        Ok(())
+    }
+
+    /**
+     * lines of the current page that are too long for the display, by index and the characters
+     * left over past the line width
+     */
+    fn long_lines(&self) -> Vec<(usize, usize)> {
+        match self.current {
+            Some(i) if self.content.marquee(i) => {
+                match self.content.get(i) {
+                    Some(Page::Text{ lines, .. }) => lines.iter()
+                        .map(|line| line.chars().count())
+                        .enumerate()
+                        .skip(self.top)
+                        .take(self.content.line_limit())
+                        .map(|(k, len)| (k, len.saturating_sub(self.content.line_width_at(i, k))))
+                        .filter(|(_, over)| *over > 0)
+                        .collect(),
+                    _ => Vec::new()
+                }
+            }
+            _ => Vec::new()
+        }
+    }
+
+    /**
+     * move the scrolling lines that are due one character on, after a pause at the end
+     * they start over; returns whether any line moved
+     */
+    fn step_marquee(&mut self, now: Instant) -> bool {
+        let (step, pause) = self.setup.marquee();
+        let mut moved = false;
+        for (k, last) in self.long_lines() {
+            if let Some(scroll) = self.scroll.get_mut(k) {
+                if now < scroll.next {
+                    continue;
+                }
+                if scroll.offset >= last {
+                    scroll.offset = 0;
+                    scroll.next = now + pause;
+                } else {
+                    scroll.offset += 1;
+                    scroll.next = now + if scroll.offset == last { pause } else { step };
+                }
+                moved = true;
+            }
+        }
+        moved
+    }
+
+    /**
+     * when the next scrolling line moves
+     */
+    fn next_marquee(&self) -> Option<Instant> {
+        self.long_lines()
+            .into_iter()
+            .filter_map(|(k, _)| self.scroll.get(k).map(|s| s.next))
+            .min()
+    }
+
+    /**
+     * interrupt the rotation with `text`, for `duration` millisecond or until acknowledged if 0;
+     * text longer than a line is word wrapped over the page and loses its highlighting like any paragraph
+     */
+    fn alert(&mut self, duration: u32, invert: bool, blink: bool, text: String) -> aio::Result<()> {
+        let now = Instant::now();
+        let (text, spans) = contents::parse_markup(&text);
+        let line_limit = self.content.line_limit();
+        let line_width = self.content.line_width();
+        let len = text.chars().count();
+        let mut page = Page::new_text(line_limit);
+        if len <= line_width {
+            page.set_marked_text(0, text, spans);
+        } else if !page.set_paragraph(0, contents::wrap_text(&text, line_width)) {
+            return Err(aio::Error::other(OperationError::TextTooLong(len, line_limit * line_width).to_string()));
+        }
+        self.alert = Some(Alert {
+            page,
+            until: if duration > 0 { Some(now + Duration::from_millis(duration as u64)) } else { None },
+            invert,
+            blink,
+            blinked: false,
+            next_blink: now + BLINK_INTERVAL,
+        });
+        self.show()
+    }
+
+    /**
+     * drop the alert and resume the rotation with the page shown before it
+     */
+    fn dismiss(&mut self) -> aio::Result<()> {
+        if self.alert.take().is_some() {
+            self.due = Instant::now() + self.dwell(self.current.unwrap_or(0));
+            self.show()?;
+        }
+        Ok(())
+    }
+
+    /**
+     * how long page `i` stays before the next one is shown,
+     * a long page stays at least until it has scrolled to the bottom and paused there
+     */
+    fn dwell(&self, i: usize) -> Duration {
+        let dwell = match self.content.dwell(i) {
+            Some(ms) => Duration::from_millis(ms as u64),
+            None => self.setup.interval(),
+        };
+        match self.extra_lines(i) {
+            0 => dwell,
+            extra => {
+                let (step, pause) = self.setup.scroll();
+                std::cmp::max(dwell, pause * 2 + step * (extra - 1) as u32)
+            }
+        }
+    }
+
+    /**
+     * drop whatever has expired, redrawing the shown page if it changed or is gone
+     */
+    fn expire(&mut self) -> aio::Result<()> {
+        let tracked = self.tracked();
+        if !self.content.expire(Instant::now()) {
+            return Ok(());
+        }
+        if let Err(e) = self.save() {
+            eprintln!("cannot save state: {}", e);
+        }
+        self.follow(tracked);
+        self.show()
+    }
+
+    /**
+     * the shown page and the one the rotation shows next, to be found again by `follow`
+     */
+    fn tracked(&self) -> (Option<u64>, Option<u64>) {
+        let shown = self.current.and_then(|i| self.content.id(i));
+        (shown, self.content.id(self.index))
+    }
+
+    /**
+     * point `current` and `index` at the pages they were on before pages were inserted, moved,
+     * swapped or removed; if the shown page is gone the rotation moves on to the next one,
+     * returns true if another page is to be drawn
+     */
+    fn follow(&mut self, (shown, next): (Option<u64>, Option<u64>)) -> bool {
+        let n = self.content.len();
+        self.index = match next.and_then(|id| self.content.position(id)) {
+            Some(i) => i,
+            None if self.index < n => self.index,
+            None => 0,
+        };
+        match (shown, shown.and_then(|id| self.content.position(id))) {
+            (_, Some(i)) => {
+                self.current = Some(i);
+                false
+            }
+            (Some(_), None) if n > 0 => {
+                self.advance(Instant::now());
+                true
+            }
+            (Some(_), None) => {
+                self.set_current(None);
+                true
+            }
+            (None, None) => false,
+        }
+    }
+
+    /**
+     * show the page at `index` for its dwell time and move the rotation past it
+     */
+    fn advance(&mut self, now: Instant) {
+        let n = self.content.len();
+        let i = self.index % n;
+        self.due = now + self.dwell(i);
+        self.set_current(Some(i));
+        self.index = (i + 1) % n;
+    }
+
+    fn save(&self) -> aio::Result<()> {
+        match &self.state {
+            Some(path) => self.content.save(path),
+            None => Ok(())
+        }
+    }
+
+    /**
+     * rebuild the canvas from reloaded settings, pages in `content` are kept
+     * but fitted to the new number of lines;
+     * if the new canvas cannot be set up the previous settings are restored
+     */
+    fn reload(&mut self) -> aio::Result<()> {
+        self.setup.reload()?;
+        // release the device first, the new canvas may need the same bus and gpio lines
+        self.canvas = Box::new(Detached);
+        let built = self.setup.build_canvas();
+        if let Err(e) = built.and_then(|canvas| self.attach(canvas)) {
+            self.canvas = Box::new(Detached);
+            self.setup.rollback();
+            let canvas = self.setup.build_canvas()?;
+            self.attach(canvas)?;
+            return Err(e);
+        }
+        if self.content.set_text_layout(self.setup.text_layout()) {
+            if let Err(e) = self.save() {
+                eprintln!("cannot save state: {}", e);
+            }
+            self.show()?;
+        }
+        Ok(())
+    }
+
+    /**
+     * `*<count>` followed by `+pages:<n>`, `+current:<i>` (`-` if nothing is shown yet),
+     * `+interval:<ms>`, `+alert:<ms>` (`ack` if it waits for acknowledgement, `-` if there is none) and one `+<i>:<kind>:<lines>:<ms>:<ttl>:<name>` or `+<i>:BImage:<w>x<h>:<ms>:<ttl>:<name>` per page,
+     * `<ms>` being how long the page is shown, `<ttl>` the millisecond left until the page
+     * or one of its lines expires and `<name>` the page name (`-` if nothing expires or there is no name)
+     */
+    fn write_status(&self, resp: &mut String) {
+        let content = &self.content;
+        write!(resp, "*{}\r\n", content.len() + 4).unwrap();
+        write!(resp, "+pages:{}\r\n", content.len()).unwrap();
+        match self.current {
+            Some(i) => write!(resp, "+current:{}\r\n", i).unwrap(),
+            None => write!(resp, "+current:-\r\n").unwrap(),
+        }
+        write!(resp, "+interval:{}\r\n", self.setup.interval().as_millis()).unwrap();
+        match &self.alert {
+            Some(Alert{ until: Some(until), .. }) => {
+                let left = until.saturating_duration_since(Instant::now());
+                write!(resp, "+alert:{}\r\n", left.as_millis()).unwrap()
+            }
+            Some(_) => write!(resp, "+alert:ack\r\n").unwrap(),
+            None => write!(resp, "+alert:-\r\n").unwrap(),
+        }
+        let now = Instant::now();
+        for i in 0 .. content.len() {
+            let page = content.get(i).unwrap();
+            let dwell = self.dwell(i).as_millis();
+            let ttl = match content.expiry(i) {
+                Some(at) => at.saturating_duration_since(now).as_millis().to_string(),
+                None => String::from("-"),
+            };
+            let name = content.name(i).unwrap_or("-");
+            match page {
+                Page::BImage{ w, h, .. } => write!(resp, "+{}:{}:{}x{}:{}:{}:{}\r\n", i, page.kind(), w, h, dwell, ttl, name).unwrap(),
+                Page::Chart(chart) => write!(resp, "+{}:{}:{}:{}:{}:{}\r\n", i, page.kind(), chart.samples.len(), dwell, ttl, name).unwrap(),
+                _ => write!(resp, "+{}:{}:{}:{}:{}:{}\r\n", i, page.kind(), page.line_num(), dwell, ttl, name).unwrap(),
+            }
+        }
+    }
+}
+
 
 impl Handler for Manager {
 
@@ -58,12 +597,43 @@ impl Handler for Manager {
             let sp = CommandSplit::new(read.get(read.readable()), &mut index);
             let mut resp = String::with_capacity(256);
             let mut inner = self.inner.borrow_mut();
+            inner.expire()?;
+            let tracked = inner.tracked();
+            let mut modified = false;
+            // the page on the display changed and has to be drawn again
+            let mut redraw = false;
             for s in sp {
                 match Operation::new(s) {
+                    Ok(Operation::QueryStatus) => {
+                        inner.write_status(&mut resp);
+                    }
+                    Ok(Operation::Alert{ duration, invert, blink, text }) => {
+                        match inner.alert(duration, invert, blink, text) {
+                            Ok(()) => write!(&mut resp, "+\r\n").unwrap(),
+                            Err(e) => write!(&mut resp, "-{}\r\n", e).unwrap(),
+                        }
+                    }
+                    Ok(Operation::Acknowledge) => {
+                        match inner.dismiss() {
+                            Ok(()) => write!(&mut resp, "+\r\n").unwrap(),
+                            Err(e) => write!(&mut resp, "-{}\r\n", e).unwrap(),
+                        }
+                    }
+                    Ok(Operation::Reload) => {
+                        match inner.reload() {
+                            Ok(()) => write!(&mut resp, "+\r\n").unwrap(),
+                            Err(e) => write!(&mut resp, "-{}\r\n", e).unwrap(),
+                        }
+                    }
                     Ok(op) => {
+                        let mutation = op.is_mutation();
+                        // by identity, earlier commands may have moved the shown page
+                        let shown = op.target(&inner.content).and_then(|i| inner.content.id(i)).is_some_and(|id| tracked.0 == Some(id));
                         match op.modify(&mut inner.content) {
                             Ok(query) => {
-                                write!(&mut resp, "+{}\r\n", query.get_text()).unwrap();
+                                write!(&mut resp, "{}", query).unwrap();
+                                modified |= mutation;
+                                redraw |= shown;
                             }
     
                             Err(e) => {
@@ -77,33 +647,312 @@ impl Handler for Manager {
                 }
             }
             read.skip(index);
+            if modified {
+                if let Err(e) = inner.save() {
+                    eprintln!("cannot save state: {}", e);
+                }
+            }
+            if inner.follow(tracked) || redraw {
+                inner.show()?;
+            }
             resp
         };
 
         {
             let mut inner = self.inner.borrow_mut();
-            let content = &inner.content;
-            if content.len() == 1 {
-                let page = content.get(0).unwrap().clone();
-                inner.canvas.draw(&page)?;
-                inner.canvas.flush()?;
+            if inner.content.len() == 1 {
+                inner.set_current(Some(0));
+                inner.show()?;
             }
         }
 
         Ok(Buf::from(resp.into_bytes()))      
     }
 
+    /**
+     * shows the next page once the current one has stayed for its dwell time,
+     * while an alert is up only its blinking and timeout are handled
+     */
     fn handle_schedule(&self) -> aio::Result<()> {
         let mut inner = self.inner.borrow_mut();
-        let content = &inner.content;
-        let n = content.len();
+        inner.expire()?;
+        let now = Instant::now();
+        if let Some(alert) = &mut inner.alert {
+            if alert.until.is_some_and(|until| now >= until) {
+                inner.dismiss()?;
+            } else if alert.blink && now >= alert.next_blink {
+                alert.blinked = !alert.blinked;
+                alert.next_blink = now + BLINK_INTERVAL;
+                inner.show()?;
+            }
+            return Ok(());
+        }
+        if now < inner.due {
+            return inner.animate(now);
+        }
+        let n = inner.content.len();
         if n > 1 {
-            let i = inner.index;
-            let page = content.get(i).unwrap().clone();
-            inner.canvas.draw(&page)?;
-            inner.canvas.flush()?;
-            inner.index = (i + 1) % n;
+            inner.advance(now);
+            inner.show()?;
+        } else {
+            inner.due = now + inner.setup.interval();
+            inner.animate(now)?;
+        }
+        Ok(())
+    }
+
+    fn handle_reload(&self) -> aio::Result<()> {
+        self.inner.borrow_mut().reload()
+    }
+
+    fn handle_overflow(&self) -> Buf {
+        Buf::from(format!("-{}\r\n", ParseError::LineTooLong).into_bytes())
+    }
+
+    /**
+     * wakes up for the next page, expiry, alert change or marquee step, whichever comes first
+     */
+    fn next_schedule(&self) -> Duration {
+        let inner = self.inner.borrow();
+        let now = Instant::now();
+        // the rotation is held while an alert is up, so only its own deadlines count then
+        let at = match &inner.alert {
+            Some(alert) => alert.until.into_iter()
+                .chain(alert.blink.then_some(alert.next_blink))
+                .min(),
+            None => inner.next_marquee().into_iter()
+                .chain(inner.next_top(now))
+                .chain(inner.next_text_blink(now))
+                .chain(Some(inner.due))
+                .min(),
+        };
+        let mut next = at.map_or(IDLE_SCHEDULE, |at| at.saturating_duration_since(now));
+        if let Some(at) = inner.content.next_expiry() {
+            next = std::cmp::min(next, at.saturating_duration_since(now));
         }
+        next
+    }
+}
+
+/**
+ * `line` with the characters of its blinking spans replaced by spaces and those spans dropped
+ */
+fn hide_blinking(line: &str, style: LineStyle) -> (String, LineStyle) {
+    let hidden = |k: usize| style.spans.iter().any(|span| span.attrs.blink && span.start <= k && k < span.end);
+    let line = line.chars()
+        .enumerate()
+        .map(|(k, c)| if hidden(k) { ' ' } else { c })
+        .collect();
+    let spans = style.spans.iter().filter(|span| !span.attrs.blink).copied().collect();
+    (line, LineStyle{ spans, ..style })
+}
+
+/**
+ * stands in while the canvas is being rebuilt
+ */
+struct Detached;
+
+impl Canvas for Detached {
+
+    fn draw(&mut self, _page: &Page) -> aio::Result<()> {
         Ok(())
     }
+
+    fn init(&mut self) -> aio::Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> aio::Result<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> aio::Result<()> {
+        Ok(())
+    }
+
+    fn set_invert(&mut self, _invert: bool) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed;
+
+    impl Setup for Fixed {
+
+        fn reload(&mut self) -> aio::Result<()> {
+            Ok(())
+        }
+
+        fn rollback(&mut self) {
+        }
+
+        fn build_canvas(&self) -> aio::Result<Box<dyn Canvas>> {
+            Ok(Box::new(Detached))
+        }
+
+        fn interval(&self) -> Duration {
+            Duration::from_secs(5)
+        }
+
+        fn text_layout(&self) -> TextLayout {
+            TextLayout{ lines: 3, width: 128, height: 32, interval: 10, font: contents::TextFont::Font6x8 }
+        }
+
+        fn marquee(&self) -> (Duration, Duration) {
+            (Duration::from_millis(300), Duration::from_secs(1))
+        }
+
+        fn scroll(&self) -> (Duration, Duration) {
+            (Duration::from_secs(1), Duration::from_secs(2))
+        }
+    }
+
+    /// keeps the first line of every page drawn
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Canvas for Recorder {
+
+        fn draw(&mut self, page: &Page) -> aio::Result<()> {
+            self.0.borrow_mut().push(String::from(page.get_text(0).unwrap_or_default()));
+            Ok(())
+        }
+
+        fn init(&mut self) -> aio::Result<()> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> aio::Result<()> {
+            Ok(())
+        }
+
+        fn clear(&mut self) -> aio::Result<()> {
+            Ok(())
+        }
+
+        fn set_invert(&mut self, _invert: bool) {
+        }
+    }
+
+    /// text pages named after `names`, with the second one on the display
+    fn manager(names: &[&str]) -> Manager {
+        let mut content = Content::new(Fixed.text_layout());
+        for (i, name) in names.iter().enumerate() {
+            content.set(i, Page::new_text(3));
+            content.set_name(i, Some(String::from(*name)));
+        }
+        let manager = Manager::new(content, Box::new(Fixed), None).unwrap();
+        {
+            let mut inner = manager.inner.borrow_mut();
+            inner.current = Some(1);
+            inner.index = 2 % names.len();
+        }
+        manager
+    }
+
+    fn send(manager: &Manager, line: &str) -> String {
+        let data = format!("{}\r\n", line).into_bytes().into_boxed_slice().into_vec();
+        let resp = manager.handle_network(&mut Buf::from(data)).unwrap();
+        String::from_utf8_lossy(resp.get(resp.readable())).into_owned()
+    }
+
+    /// names of the shown page and of the one the rotation shows next
+    fn rotation(manager: &Manager) -> (Option<String>, Option<String>) {
+        let inner = manager.inner.borrow();
+        let name = |i: usize| inner.content.name(i).map(String::from);
+        (inner.current.and_then(name), name(inner.index))
+    }
+
+    fn names(shown: &str, next: &str) -> (Option<String>, Option<String>) {
+        (Some(String::from(shown)), Some(String::from(next)))
+    }
+
+    #[test]
+    fn follow_keeps_the_shown_page_when_it_moves() {
+        let manager = manager(&["a", "b", "c"]);
+        assert_eq!(send(&manager, "@0>2"), "+\r\n");
+        assert_eq!(rotation(&manager), names("b", "c"));
+        assert_eq!(manager.inner.borrow().current, Some(0));
+        assert_eq!(send(&manager, "@0&2"), "+\r\n");
+        assert_eq!(rotation(&manager), names("b", "c"));
+    }
+
+    #[test]
+    fn follow_keeps_the_shown_page_when_one_is_inserted_before_it() {
+        let manager = manager(&["a", "b", "c"]);
+        assert_eq!(send(&manager, "@0<d"), "+\r\n");
+        assert_eq!(rotation(&manager), names("b", "c"));
+        assert_eq!(manager.inner.borrow().current, Some(2));
+    }
+
+    #[test]
+    fn follow_moves_on_when_the_shown_page_is_deleted() {
+        let manager = manager(&["a", "b", "c"]);
+        assert_eq!(send(&manager, "@b~"), "+\r\n");
+        assert_eq!(rotation(&manager), names("c", "a"));
+        assert_eq!(send(&manager, "@0~"), "+\r\n");
+        assert_eq!(rotation(&manager), names("c", "c"));
+        assert_eq!(send(&manager, "@0~"), "+\r\n");
+        assert_eq!(rotation(&manager), (None, None));
+    }
+
+    #[test]
+    fn changes_to_the_shown_page_are_drawn() {
+        let manager = manager(&["a", "b", "c"]);
+        let drawn = Rc::new(RefCell::new(Vec::new()));
+        manager.inner.borrow_mut().attach(Box::new(Recorder(drawn.clone()))).unwrap();
+        drawn.borrow_mut().clear();
+        assert_eq!(send(&manager, "@c:0+other"), "+\r\n");
+        assert!(drawn.borrow().is_empty());
+        assert_eq!(send(&manager, "@b:0+shown"), "+\r\n");
+        assert_eq!(drawn.borrow().last().map(String::as_str), Some("shown"));
+        // the shown page moved down by the insert is still found by its name
+        assert_eq!(send(&manager, "@0<\r\n@b:0+moved"), "+\r\n+\r\n");
+        assert_eq!(drawn.borrow().last().map(String::as_str), Some("moved"));
+    }
+
+    #[test]
+    fn damaged_state_is_moved_aside() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("ssd1306-{}-damaged.json", std::process::id()));
+        let aside = path.with_extension("bad");
+        fs::write(&path, r#"[{"page":{"Text":{"lines":["a""#).unwrap();
+        let mut content = Content::new(Fixed.text_layout());
+        content.set(0, Page::new_text(3));
+        content.set_name(0, Some(String::from("configured")));
+        let manager = Manager::new(content, Box::new(Fixed), Some(path.clone())).unwrap();
+        assert_eq!(manager.inner.borrow().content.name(0), Some("configured"));
+        assert!(!path.exists());
+        assert!(aside.exists());
+        fs::remove_file(&aside).unwrap();
+    }
+
+    #[test]
+    fn alerts_are_wrapped_and_checked_like_text() {
+        let manager = manager(&["a"]);
+        assert_eq!(send(&manager, "@!0+disk {i:full}"), "+\r\n");
+        let lines = |manager: &Manager| {
+            let inner = manager.inner.borrow();
+            let page = &inner.alert.as_ref().unwrap().page;
+            (0 .. page.line_num()).map(|k| String::from(page.get_text(k).unwrap())).collect::<Vec<_>>()
+        };
+        assert_eq!(lines(&manager), vec!["disk full", "", ""]);
+        assert_eq!(manager.inner.borrow().alert.as_ref().unwrap().page.style(0).spans.len(), 1);
+        assert_eq!(send(&manager, "@!0+the disk of the backup server is full"), "+\r\n");
+        assert_eq!(lines(&manager), vec!["the disk of the", "backup server is full", ""]);
+        let long = "x".repeat(64);
+        assert_eq!(send(&manager, &format!("@!0+{}", long)), "-TextTooLong:64@63\r\n");
+    }
+
+    #[test]
+    fn scroll_follows_the_length_of_the_shown_page() {
+        let manager = manager(&["a", "b"]);
+        assert_eq!(manager.inner.borrow().scroll.len(), 3);
+        assert_eq!(send(&manager, "@b$lines=6"), "+\r\n");
+        assert_eq!(manager.inner.borrow().scroll.len(), 6);
+        assert_eq!(send(&manager, "@b$lines=0"), "+\r\n");
+        assert_eq!(manager.inner.borrow().scroll.len(), 3);
+    }
 }
@@ -1,57 +1,256 @@
+#![allow(clippy::empty_line_after_doc_comments)]
 use std::error;
 use std::fmt;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
 use base64::DecodeError;
+use crate::contents;
 use crate::contents::Page;
 use crate::contents::Content;
+use crate::contents::PageRef;
+use crate::contents::MAX_PAGE_LINES;
+use crate::contents::MAX_CHART_SAMPLES;
+use crate::contents::TextFont;
+use crate::contents::Align;
+use crate::contents::Bar;
+use crate::contents::ChartKind;
 
 /**
  * `hello world` set text "hello world"
  * `@1:2+hello world` set page 1 line 2 = "hello world"
- * `@1:2~`  delete page 1 line 2
+ * `@cpu:0+12%` set line 0 of the page named "cpu", added at the end of the rotation if it does not exist
+ * `@1:2~`  delete page 1 line 2, the lines below scroll up
+ * `@1:2<hello world` insert "hello world" as page 1 line 2, the lines from there scroll down
+ * `@1:2-` clear page 1 line 2
  * `@1:2?` query page 1 line 2
+ * `@1:2$font=12x16` draw page 1 line 2 in the 12x16 font, `@1:2$font=default` to go back to the default one
+ * `@1:2$center` center page 1 line 2, `$right` to align it right and `$left` to go back
+ * `@1:2%73` show 73 as a progress bar from 0 to 100 on page 1 line 2, `@1:2%-5,-20,40:temp` from -20 to 40 labelled "temp"
+ * `@1:2+error {ib:42}` highlight spans of text written to a line, `i` inverted, `u` underlined, `b` blinking
  * `@1+128,32:<base64>` set page 1 with width=128,height=32, decode data from base64
  * `@1~` delete page 1
+ * `@1<` insert an empty page before page 1, `@1<cpu` also names it "cpu"
+ * `@3>1` move page 3 to position 1
+ * `@1&3` swap page 1 and page 3
+ * `@1$wrap` word wrap text written to page 1 over the following lines, `@1$nowrap` to stop
+ * `@1$marquee` let lines of page 1 that are too long scroll, `@1$nomarquee` to stop
+ * `@1$lines=30` give page 1 30 lines that scroll through while it is shown, `@1$lines=0` to fit it again
+ * `@1*42.5` add a sample to the chart on page 1 (an empty or new page becomes one), `@1?` lists the samples
+ * `@1$bars` draw the chart of page 1 as bars, `$line` as a line, `@1$samples=60` keep 60 samples,
+ * `@1$title=CPU load` show a title line above it, `$notitle` to remove it
+ * `@1=10000` show page 1 for 10000ms in the rotation, `@1=0` for the roll interval
+ * `@1/60000` remove page 1 after 60000ms, `@1:2/60000` clear page 1 line 2 after 60000ms, `/0` keeps them
+ * `@!10000ib+disk full` show an alert for 10000ms (0 until acknowledged), `i` inverted, `b` blinking
+ * `@!~` acknowledge the alert
+ * `@?` query status of the display and all pages
+ * `@#` query how many lines fit on a page and how many characters on a line
+ * `@^` reload the configuration
  */
 
-const SP_PAGE: u8 = '@' as u8;
-const SP_LINE: u8 = ':' as u8;
-const OP_DATA: u8 = '+' as u8;
-const SP_SIZE: u8 = ',' as u8;
-const OP_STRING: u8 = '+' as u8;
-const OP_BASE64: u8 = ':' as u8;
-const OP_DELETE: u8 = '~' as u8;
-const OP_QUERY: u8 = '?' as u8;
-const CRLF: (u8, u8) = ('\r' as u8, '\n' as u8);
+const SP_PAGE: u8 = b'@';
+const SP_LINE: u8 = b':';
+const OP_DATA: u8 = b'+';
+const SP_SIZE: u8 = b',';
+const OP_STRING: u8 = b'+';
+const OP_BASE64: u8 = b':';
+const OP_DELETE: u8 = b'~';
+const OP_CLEAR: u8 = b'-';
+const OP_QUERY: u8 = b'?';
+const OP_RELOAD: u8 = b'^';
+const OP_DWELL: u8 = b'=';
+const OP_TTL: u8 = b'/';
+const OP_INSERT: u8 = b'<';
+const OP_MOVE: u8 = b'>';
+const OP_SWAP: u8 = b'&';
+const OP_ALERT: u8 = b'!';
+const OP_LAYOUT: u8 = b'#';
+const OP_OPTION: u8 = b'$';
+const OP_BAR: u8 = b'%';
+const OP_SAMPLE: u8 = b'*';
+const SP_RANGE: u8 = b',';
+const SP_LABEL: u8 = b':';
+const FLAG_INVERT: u8 = b'i';
+const FLAG_BLINK: u8 = b'b';
+const CRLF: (u8, u8) = (b'\r', b'\n');
+const MAX_IMAGE_SIDE: usize = 1024;
+
+/**
+ * the longest command line that can be valid: the largest image in base64, with room for the page and its size
+ */
+pub const MAX_LINE: usize = (MAX_IMAGE_SIDE / 8 * MAX_IMAGE_SIDE).div_ceil(3) * 4 + 256;
+
+/**
+ * per page settings changed with `@<page>$<option>`
+ */
+#[derive(Debug)]
+pub enum PageOption {
+    Wrap(bool),
+    Marquee(bool),
+    Lines(usize),
+    Chart(ChartOption),
+}
+
+/**
+ * page options only chart pages have
+ */
+#[derive(Debug)]
+pub enum ChartOption {
+    Kind(ChartKind),
+    Samples(usize),
+    Title(Option<String>),
+}
+
+impl PageOption {
+
+    fn from_bytes(s: &[u8]) -> Option<Self> {
+        match s {
+            b"wrap" => Some(Self::Wrap(true)),
+            b"nowrap" => Some(Self::Wrap(false)),
+            b"marquee" => Some(Self::Marquee(true)),
+            b"nomarquee" => Some(Self::Marquee(false)),
+            b"line" => Some(Self::Chart(ChartOption::Kind(ChartKind::Line))),
+            b"bars" => Some(Self::Chart(ChartOption::Kind(ChartKind::Bars))),
+            b"notitle" => Some(Self::Chart(ChartOption::Title(None))),
+            _ => {
+                if let Some(title) = s.strip_prefix(b"title=") {
+                    let title = String::from_utf8_lossy(title).into_owned();
+                    return Some(Self::Chart(ChartOption::Title(Some(title))));
+                }
+                if let Some(n) = s.strip_prefix(b"samples=") {
+                    let n = std::str::from_utf8(n).ok()?.parse().ok()?;
+                    if n == 0 || n > MAX_CHART_SAMPLES {
+                        return None;
+                    }
+                    return Some(Self::Chart(ChartOption::Samples(n)));
+                }
+                let n = s.strip_prefix(b"lines=")?;
+                let n = std::str::from_utf8(n).ok()?.parse().ok()?;
+                if n > MAX_PAGE_LINES {
+                    return None;
+                }
+                Some(Self::Lines(n))
+            }
+        }
+    }
+}
+
+/**
+ * per line settings changed with `@<page>:<line>$<option>`
+ */
+#[derive(Debug)]
+pub enum LineOption {
+    Font(Option<TextFont>),
+    Align(Align),
+}
+
+impl LineOption {
+
+    fn from_bytes(s: &[u8]) -> Option<Self> {
+        match s {
+            b"left" => Some(Self::Align(Align::Left)),
+            b"center" => Some(Self::Align(Align::Center)),
+            b"right" => Some(Self::Align(Align::Right)),
+            _ => {
+                let font = std::str::from_utf8(s.strip_prefix(b"font=")?).ok()?;
+                match font {
+                    "default" => Some(Self::Font(None)),
+                    _ => TextFont::from_name(font).map(|font| Self::Font(Some(font)))
+                }
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum Operation {
     Pass,
     RSetText(String),
     SetText {
-        page: usize,
+        page: PageRef,
         line: usize,
         text: String,
     },
     DeleteText {
-        page: usize,
+        page: PageRef,
+        line: usize,
+    },
+    InsertText {
+        page: PageRef,
+        line: usize,
+        text: String,
+    },
+    ClearText {
+        page: PageRef,
         line: usize,
     },
     QueryText {
-        page: usize,
+        page: PageRef,
         line: usize,
     },
     SetPage {
-        page: usize,
+        page: PageRef,
         data: Vec<u8>,
         width: u32,
         height: u32,
     },
     DeletePage {
-        page: usize,
+        page: PageRef,
+    },
+    InsertPage {
+        page: PageRef,
+        name: Option<String>,
+    },
+    MovePage {
+        page: PageRef,
+        to: PageRef,
+    },
+    SwapPages {
+        page: PageRef,
+        other: PageRef,
     },
     QueryPage {
-        page: usize,
+        page: PageRef,
+    },
+    AddSample {
+        page: PageRef,
+        value: f32,
+    },
+    SetDwell {
+        page: PageRef,
+        dwell: u32,
+    },
+    SetOption {
+        page: PageRef,
+        option: PageOption,
     },
+    SetLineOption {
+        page: PageRef,
+        line: usize,
+        option: LineOption,
+    },
+    SetBar {
+        page: PageRef,
+        line: usize,
+        value: i32,
+        range: Option<(i32, i32)>,
+        label: Option<String>,
+    },
+    SetExpiry {
+        page: PageRef,
+        line: Option<usize>,
+        ttl: u32,
+    },
+    Alert {
+        duration: u32,
+        invert: bool,
+        blink: bool,
+        text: String,
+    },
+    Acknowledge,
+    QueryLayout,
+    QueryStatus,
+    Reload,
 }
 
 impl Operation {
@@ -72,22 +271,65 @@ impl Operation {
             return Err(ParseError::InvalidLine);
         }
 
-        let parse_uint = |i: usize| -> (usize, usize) {
-            const RANGE: (u8, u8) = ('0' as u8, '9' as u8);
-            let mut r = 0;
-            let mut count = 0;
-            for c in &data[i..] {
+        // digits and how many there are, numbers too large for usize are refused
+        let parse_uint = |i: usize| -> Result<(usize, usize), ParseError> {
+            const RANGE: (u8, u8) = (b'0', b'9');
+            let mut r: usize = 0;
+            for (count, c) in data[i..].iter().enumerate() {
                 let c = *c;
                 if c < RANGE.0 || c > RANGE.1 {
-                    return (r, count);
+                    return Ok((r, count));
                 }
-                r *= 10;
-                r += (c - RANGE.0) as usize;
-                count += 1;
+                r = r.checked_mul(10)
+                    .and_then(|r| r.checked_add((c - RANGE.0) as usize))
+                    .ok_or(ParseError::InvalidData(i))?;
             }
             unreachable!();
         };
 
+        // a page number or name
+        let parse_page = |i: usize| -> Result<(PageRef, usize), ParseError> {
+            match PageRef::name_len(&data[i..]) {
+                0 => {
+                    let (page, count) = parse_uint(i)?;
+                    Ok((PageRef::Index(page), count))
+                },
+                count => {
+                    let name = String::from_utf8_lossy(&data[i..i+count]).into_owned();
+                    Ok((PageRef::Name(name), count))
+                }
+            }
+        };
+
+        // a page number or name running up to the end of the line
+        let parse_last_page = |i: usize| -> Result<PageRef, ParseError> {
+            let (page, count) = parse_page(i)?;
+            if count == 0 || i + count != n - 2 {
+                return Err(ParseError::InvalidData(i));
+            }
+            Ok(page)
+        };
+
+        // a millisecond value running up to the end of the line
+        let parse_millis = |i: usize| -> Result<u32, ParseError> {
+            let (ms, count) = parse_uint(i)?;
+            if count == 0 || i + count != n - 2 || ms > u32::MAX as usize {
+                return Err(ParseError::InvalidData(i));
+            }
+            Ok(ms as u32)
+        };
+
+        // a number that may be negative
+        let parse_int = |i: usize| -> Result<(i32, usize), ParseError> {
+            let sign = if get_c(i) == b'-' { 1 } else { 0 };
+            let (value, count) = parse_uint(i + sign)?;
+            if count == 0 || value > i32::MAX as usize {
+                return Err(ParseError::InvalidData(i));
+            }
+            let value = value as i32;
+            Ok((if sign == 1 { -value } else { value }, sign + count))
+        };
+
         let get_s = |i: usize| -> &[u8] {
             let j = data.len() - 2;
             &data[i..j]
@@ -102,7 +344,48 @@ impl Operation {
 
             SP_PAGE => {
                 i += 1;
-                let (page, count) = parse_uint(i);
+                let control = match get_c(i) {
+                    OP_QUERY => Some(Operation::QueryStatus),
+                    OP_RELOAD => Some(Operation::Reload),
+                    OP_LAYOUT => Some(Operation::QueryLayout),
+                    _ => None
+                };
+                if let Some(op) = control {
+                    i += 1;
+                    if i != n - 2 {
+                        return Err(ParseError::InvalidToken(i));
+                    }
+                    return Ok(op);
+                }
+                if get_c(i) == OP_ALERT {
+                    i += 1;
+                    if get_c(i) == OP_DELETE {
+                        if i + 1 != n - 2 {
+                            return Err(ParseError::InvalidToken(i + 1));
+                        }
+                        return Ok(Operation::Acknowledge);
+                    }
+                    let (duration, count) = parse_uint(i)?;
+                    if count == 0 || duration > u32::MAX as usize {
+                        return Err(ParseError::InvalidData(i));
+                    }
+                    i += count;
+                    let mut invert = false;
+                    let mut blink = false;
+                    loop {
+                        match get_c(i) {
+                            FLAG_INVERT => invert = true,
+                            FLAG_BLINK => blink = true,
+                            OP_STRING => break,
+                            _ => return Err(ParseError::InvalidToken(i))
+                        }
+                        i += 1;
+                    }
+                    i += 1;
+                    let text = String::from_utf8_lossy(get_s(i)).into_owned();
+                    return Ok(Operation::Alert{ duration: duration as u32, invert, blink, text });
+                }
+                let (page, count) = parse_page(i)?;
                 if count == 0 {
                     return Err(ParseError::InvalidData(i));
                 }
@@ -112,7 +395,7 @@ impl Operation {
 
                     SP_LINE => {
                         i += 1;
-                        let (line, count) = parse_uint(i);
+                        let (line, count) = parse_uint(i)?;
                         if count == 0 {
                             return Err(ParseError::InvalidData(i));
                         }
@@ -122,18 +405,58 @@ impl Operation {
                             OP_STRING => {
                                 i += 1;
                                 let text = String::from_utf8_lossy(get_s(i)).into_owned();
-                                return Ok(Operation::SetText{ page, line, text });
+                                Ok(Operation::SetText{ page, line, text })
                             },
                             OP_DELETE => {
+                                Ok(Operation::DeleteText{ page, line })
+                            },
+                            OP_INSERT => {
                                 i += 1;
-                                return Ok(Operation::DeleteText{ page, line });
+                                let text = String::from_utf8_lossy(get_s(i)).into_owned();
+                                Ok(Operation::InsertText{ page, line, text })
+                            },
+                            OP_CLEAR => {
+                                Ok(Operation::ClearText{ page, line })
                             },
                             OP_QUERY => {
+                                Ok(Operation::QueryText{ page, line })
+                            },
+                            OP_TTL => {
+                                let ttl = parse_millis(i + 1)?;
+                                Ok(Operation::SetExpiry{ page, line: Some(line), ttl })
+                            },
+                            OP_OPTION => {
                                 i += 1;
-                                return Ok(Operation::QueryText{ page, line });
+                                let option = LineOption::from_bytes(get_s(i)).ok_or(ParseError::InvalidData(i))?;
+                                Ok(Operation::SetLineOption{ page, line, option })
+                            },
+                            OP_BAR => {
+                                i += 1;
+                                let (value, count) = parse_int(i)?;
+                                i += count;
+                                let mut range = None;
+                                if get_c(i) == SP_RANGE {
+                                    let (min, count) = parse_int(i + 1)?;
+                                    i += 1 + count;
+                                    if get_c(i) != SP_RANGE {
+                                        return Err(ParseError::InvalidToken(i));
+                                    }
+                                    let (max, count) = parse_int(i + 1)?;
+                                    if max <= min {
+                                        return Err(ParseError::InvalidData(i + 1));
+                                    }
+                                    i += 1 + count;
+                                    range = Some((min, max));
+                                }
+                                let label = match get_c(i) {
+                                    SP_LABEL => Some(String::from_utf8_lossy(get_s(i + 1)).into_owned()),
+                                    _ if i == n - 2 => None,
+                                    _ => return Err(ParseError::InvalidToken(i))
+                                };
+                                Ok(Operation::SetBar{ page, line, value, range, label })
                             },
                             _ => {
-                                return Err(ParseError::InvalidToken(i));
+                                Err(ParseError::InvalidToken(i))
                             }
                         }
                     },
@@ -141,8 +464,8 @@ impl Operation {
                     OP_DATA => {
                         i += 1;
                         
-                        let (width, count) = parse_uint(i);
-                        if count == 0 {
+                        let (width, count) = parse_uint(i)?;
+                        if count == 0 || width > MAX_IMAGE_SIDE {
                             return Err(ParseError::InvalidData(i));
                         }
                         i += count;
@@ -153,8 +476,8 @@ impl Operation {
                         }
                         i += 1;
 
-                        let (height, count) = parse_uint(i);
-                        if count == 0 {
+                        let (height, count) = parse_uint(i)?;
+                        if count == 0 || height > MAX_IMAGE_SIDE {
                             return Err(ParseError::InvalidData(i));
                         }
                         i += count;
@@ -166,96 +489,453 @@ impl Operation {
                         i += 1;
 
                         let data = base64::decode(get_s(i)).map_err(|e| ParseError::InvalidBase64(i, e))?;
-                        return Ok(Operation::SetPage{ page, width: width as u32, height: height as u32, data});
+                        Ok(Operation::SetPage{ page, width: width as u32, height: height as u32, data})
                     },
 
                     OP_DELETE => {
+                        Ok(Operation::DeletePage{ page })
+                    },
+
+                    OP_QUERY => {
+                        Ok(Operation::QueryPage{ page })
+                    },
+
+                    OP_INSERT => {
+                        i += 1;
+                        if i == n - 2 {
+                            return Ok(Operation::InsertPage{ page, name: None });
+                        }
+                        match parse_last_page(i)? {
+                            PageRef::Name(name) => Ok(Operation::InsertPage{ page, name: Some(name) }),
+                            PageRef::Index(_) => Err(ParseError::InvalidData(i)),
+                        }
+                    },
+
+                    OP_MOVE => {
+                        let to = parse_last_page(i + 1)?;
+                        Ok(Operation::MovePage{ page, to })
+                    },
+
+                    OP_SWAP => {
+                        let other = parse_last_page(i + 1)?;
+                        Ok(Operation::SwapPages{ page, other })
+                    },
+
+                    OP_OPTION => {
+                        i += 1;
+                        let option = PageOption::from_bytes(get_s(i)).ok_or(ParseError::InvalidData(i))?;
+                        Ok(Operation::SetOption{ page, option })
+                    },
+
+                    OP_SAMPLE => {
                         i += 1;
-                        return Ok(Operation::DeletePage{ page });
+                        let value = std::str::from_utf8(get_s(i)).ok()
+                            .and_then(|s| s.parse::<f32>().ok())
+                            .filter(|value| value.is_finite())
+                            .ok_or(ParseError::InvalidData(i))?;
+                        Ok(Operation::AddSample{ page, value })
+                    },
+
+                    OP_DWELL => {
+                        let dwell = parse_millis(i + 1)?;
+                        Ok(Operation::SetDwell{ page, dwell })
+                    },
+
+                    OP_TTL => {
+                        let ttl = parse_millis(i + 1)?;
+                        Ok(Operation::SetExpiry{ page, line: None, ttl })
                     },
 
                     _ => {
                         i += 1;
-                        return Err(ParseError::InvalidToken(i))
+                        Err(ParseError::InvalidToken(i))
                     }
                 }
             },
 
             _ => {
                 let text = String::from_utf8_lossy(get_s(i)).into_owned();
-                return Ok(Operation::RSetText(text));
+                Ok(Operation::RSetText(text))
             }
         }
     }
 
-    pub fn modify(self, content: &mut Content) -> Result<QueryData, OperationError> {
+    /**
+     * whether a successful `modify` changes the content
+     */
+    pub fn is_mutation(&self) -> bool {
+        matches!(self,
+            Self::RSetText(_) | Self::SetText{ .. } | Self::DeleteText{ .. } | Self::InsertText{ .. } | Self::SetBar{ .. } |
+            Self::ClearText{ .. } | Self::SetPage{ .. } |
+            Self::DeletePage{ .. } | Self::InsertPage{ .. } | Self::MovePage{ .. } | Self::SwapPages{ .. } |
+            Self::AddSample{ .. } | Self::SetDwell{ .. } | Self::SetOption{ .. } | Self::SetLineOption{ .. } | Self::SetExpiry{ .. }
+        )
+    }
+
+    /**
+     * position of the page whose look a change alters, `None` for changes that add, move or drop pages
+     */
+    pub fn target(&self, content: &Content) -> Option<usize> {
+        let page = match self {
+            Self::RSetText(_) => return Some(0),
+            Self::SetText{ page, .. } | Self::DeleteText{ page, .. } | Self::InsertText{ page, .. } |
+            Self::ClearText{ page, .. } | Self::SetPage{ page, .. } | Self::AddSample{ page, .. } |
+            Self::SetOption{ page, .. } | Self::SetLineOption{ page, .. } | Self::SetBar{ page, .. } |
+            Self::SetExpiry{ page, .. } => page,
+            _ => return None,
+        };
+        match page {
+            PageRef::Index(i) => Some(*i),
+            PageRef::Name(name) => content.find(name),
+        }
+    }
+
+    pub fn modify(self, content: &mut Content) -> Result<QueryData<'_>, OperationError> {
         match self {
             Self::Pass => Ok(QueryData::None),
             Self::SetText{ page, line, text } => {
                 let line_limit = content.line_limit();
-                if line < line_limit {
-                    if let Some(page) = content.get_mut_or_add(page, || { Page::new_text(line_limit) }) {
-                        page.set_text(line, text);
+                let (i, name) = locate_or_add(content, page);
+                let page_lines = content.page_lines(i);
+                // a paragraph reflows at the width of each line it lands on
+                let widths: Vec<usize> = (line .. page_lines).map(|k| content.line_width_at(i, k)).collect();
+                let (text, spans) = contents::parse_markup(&text);
+                if line < page_lines {
+                    let wrap = content.wrap(i);
+                    if !wrap && !content.marquee(i) {
+                        check_width(&text, content.line_width_at(i, line))?;
+                    }
+                    let paragraph = wrap.then(|| contents::wrap_text_by(&text, |k| widths.get(k).copied().unwrap_or(0)));
+                    // text on a blank line brings it back on the display below the lines in bigger fonts
+                    check_height(content, i, |page| match &paragraph {
+                        Some(paragraph) => page.set_paragraph(line, paragraph.clone()),
+                        None => page.set_text(line, text.clone()),
+                    })?;
+                    if let Some(page) = content.get_mut_or_add(i, || { Page::new_text(line_limit) }) {
+                        if page.line_num() <= line {
+                            return Err(OperationError::LineOutOfPage(line, page.line_num()));
+                        }
+                        // a wrapped paragraph is not highlighted, its spans would not survive the line breaks
+                        if let Some(paragraph) = paragraph {
+                            if !page.set_paragraph(line, paragraph) {
+                                let room = widths.iter().take(page.line_num() - line).sum();
+                                return Err(OperationError::TextTooLong(text.chars().count(), room));
+                            }
+                        } else {
+                            page.set_marked_text(line, text, spans);
+                        }
+                        content.set_name(i, name);
                         Ok(QueryData::None)
                     } else {
-                        Err(OperationError::PageOutOfBound(page, content.len()))
+                        Err(OperationError::PageOutOfBound(i, content.len()))
                     }
                 } else {
-                    Err(OperationError::LineOutOfPage(line, line_limit))
+                    Err(OperationError::LineOutOfPage(line, page_lines))
                 }                
             },
+            Self::SetBar{ page, line, value, range, label } => {
+                let line_limit = content.line_limit();
+                let (i, name) = locate_or_add(content, page);
+                let page_lines = content.page_lines(i);
+                if line >= page_lines {
+                    return Err(OperationError::LineOutOfPage(line, page_lines));
+                }
+                if let Some(label) = &label {
+                    check_width(label, content.line_width_at(i, line))?;
+                }
+                // a bar keeps its range when only the value changes
+                let (min, max) = range
+                    .or_else(|| content.get(i).and_then(|page| page.style(line).bar).map(|bar| (bar.min, bar.max)))
+                    .unwrap_or((0, 100));
+                let bar = Bar{ value, min, max };
+                check_height(content, i, |page| page.set_bar(line, label.clone(), bar))?;
+                if let Some(page) = content.get_mut_or_add(i, || { Page::new_text(line_limit) }) {
+                    if page.set_bar(line, label, bar) {
+                        content.set_name(i, name);
+                        Ok(QueryData::None)
+                    } else {
+                        Err(OperationError::LineOutOfPage(line, page.line_num()))
+                    }
+                } else {
+                    Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
             Self::DeleteText{ page, line } => {
-                if let Some(page) = content.get_mut(page) {
+                let i = locate(content, page)?;
+                check_height(content, i, |page| page.remove_text(line))?;
+                if let Some(page) = content.get_mut(i) {
                     if page.remove_text(line) {
+                        content.line_removed(i, line);
+                        Ok(QueryData::None)
+                    } else {
+                        Err(OperationError::LineOutOfPage(line, page.line_num()))
+                    }
+                } else {
+                    Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::InsertText{ page, line, text } => {
+                let i = locate(content, page)?;
+                let (text, spans) = contents::parse_markup(&text);
+                if !content.marquee(i) {
+                    check_width(&text, content.line_width())?;
+                }
+                check_height(content, i, |page| page.insert_text(line, text.clone()))?;
+                if let Some(page) = content.get_mut(i) {
+                    if page.insert_text(line, String::default()) {
+                        page.set_marked_text(line, text, spans);
+                        content.line_inserted(i, line);
                         Ok(QueryData::None)
                     } else {
                         Err(OperationError::LineOutOfPage(line, page.line_num()))
                     }
                 } else {
-                    Err(OperationError::PageOutOfBound(page, content.len()))
+                    Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::ClearText{ page, line } => {
+                let i = locate(content, page)?;
+                if let Some(page) = content.get_mut(i) {
+                    if page.set_text(line, String::default()) {
+                        content.set_expiry(i, Some(line), None);
+                        Ok(QueryData::None)
+                    } else {
+                        Err(OperationError::LineOutOfPage(line, page.line_num()))
+                    }
+                } else {
+                    Err(OperationError::PageOutOfBound(i, content.len()))
                 }
             },
             Self::QueryText{ page, line } => {
-                if let Some(page) = content.get(page) {
+                let i = locate(content, page)?;
+                if let Some(page) = content.get(i) {
                     if let Some(s) = page.get_text(line) {
                         Ok(QueryData::Text(s))
                     } else {
                         Err(OperationError::LineOutOfPage(line, page.line_num()))
                     }
                 } else {
-                    Err(OperationError::PageOutOfBound(page, content.len()))
+                    Err(OperationError::PageOutOfBound(i, content.len()))
                 }
             },
             Self::RSetText(text) => {
-                let line_limit = content.line_limit();
-                if 0 < line_limit {
-                    if let Some(page) = content.get_mut_or_add(0, || { Page::new_text(line_limit) }) {
-                        page.set_text(0, text);
+                Self::SetText{ page: PageRef::Index(0), line: 0, text }.modify(content)
+            },
+            Self::SetPage{ page, data, width, height } => {
+                let len = data.len();
+                if let Some(image) = Page::from_image(data, width, height) {
+                    let (i, name) = locate_or_add(content, page);
+                    if content.set(i, image) {
+                        content.set_name(i, name);
                         Ok(QueryData::None)
                     } else {
-                        Err(OperationError::PageOutOfBound(0, content.len()))
+                        Err(OperationError::PageOutOfBound(i, content.len()))
                     }
                 } else {
-                    Err(OperationError::LineOutOfPage(0, line_limit))
+                    Err(OperationError::ImageSizeMismatch(len, Page::image_size(width, height).unwrap_or(usize::MAX)))
                 }
             },
-            Self::SetPage{ page, data, width, height } => {
-                Err(OperationError::Invalid)
-            },
             Self::DeletePage{ page } => {
-                if let Some(page) = content.remove(page) {
+                let i = locate(content, page)?;
+                if content.remove(i).is_some() {
                     Ok(QueryData::None)
                 } else {
-                    Err(OperationError::PageOutOfBound(0, content.len()))
+                    Err(OperationError::PageOutOfBound(i, content.len()))
                 }
             },
-            Self::QueryPage{ page } => {
+            Self::InsertPage{ page, name } => {
+                let i = locate(content, page)?;
+                if let Some(name) = name.as_deref().filter(|name| content.find(name).is_some()) {
+                    return Err(OperationError::NameInUse(String::from(name)));
+                }
+                if content.insert(i, Page::new_text(content.line_limit())) {
+                    content.set_name(i, name);
+                    Ok(QueryData::None)
+                } else {
+                    Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::MovePage{ page, to } => {
+                let (i, j) = (locate(content, page)?, locate(content, to)?);
+                if content.move_page(i, j) {
+                    Ok(QueryData::None)
+                } else {
+                    Err(OperationError::PageOutOfBound(std::cmp::max(i, j), content.len()))
+                }
+            },
+            Self::SwapPages{ page, other } => {
+                let (i, j) = (locate(content, page)?, locate(content, other)?);
+                if content.swap(i, j) {
+                    Ok(QueryData::None)
+                } else {
+                    Err(OperationError::PageOutOfBound(std::cmp::max(i, j), content.len()))
+                }
+            },
+            Self::SetDwell{ page, dwell } => {
+                let i = locate(content, page)?;
+                let dwell = if dwell > 0 { Some(dwell) } else { None };
+                if content.set_dwell(i, dwell) {
+                    Ok(QueryData::None)
+                } else {
+                    Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::AddSample{ page, value } => {
+                let (i, name) = locate_or_add(content, page);
+                let capacity = content.chart_capacity();
+                match content.get_mut_or_add(i, || Page::Empty).map(|page| page.as_chart(capacity)) {
+                    Some(Some(chart)) => {
+                        chart.push(value);
+                        content.set_name(i, name);
+                        Ok(QueryData::None)
+                    }
+                    Some(None) => Err(OperationError::NotAChart(i)),
+                    None => Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::SetOption{ page, option: PageOption::Chart(option) } => {
+                let i = locate(content, page)?;
+                let capacity = content.chart_capacity();
+                match content.get_mut(i).map(|page| page.as_chart(capacity)) {
+                    Some(Some(chart)) => {
+                        match option {
+                            ChartOption::Kind(kind) => chart.kind = kind,
+                            ChartOption::Samples(n) => chart.set_capacity(n),
+                            ChartOption::Title(title) => chart.title = title,
+                        }
+                        Ok(QueryData::None)
+                    }
+                    Some(None) => Err(OperationError::NotAChart(i)),
+                    None => Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::SetOption{ page, option } => {
+                let i = locate(content, page)?;
+                let done = match option {
+                    PageOption::Wrap(wrap) => content.set_wrap(i, wrap),
+                    PageOption::Marquee(marquee) => content.set_marquee(i, marquee),
+                    PageOption::Lines(n) => {
+                        let line_limit = content.line_limit();
+                        check_height(content, i, |page| {
+                            page.resize_text(std::cmp::max(n, line_limit));
+                            true
+                        })?;
+                        content.set_length(i, if n > 0 { Some(n) } else { None })
+                    },
+                    PageOption::Chart(_) => unreachable!(),
+                };
+                if done {
+                    Ok(QueryData::None)
+                } else {
+                    Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::SetLineOption{ page, line, option } => {
+                let i = locate(content, page)?;
+                // the text already on the line has to fit the new font
+                let line_width = match option {
+                    LineOption::Font(font) if !content.wrap(i) && !content.marquee(i) => Some(content.font_width(font)),
+                    _ => None
+                };
+                // and the lines have to stay on the display in it
+                if let LineOption::Font(font) = option {
+                    check_height(content, i, |page| page.style_mut(line).map(|style| style.font = font).is_some())?;
+                }
+                if let Some(page) = content.get_mut(i) {
+                    if let (Some(text), Some(line_width)) = (page.get_text(line), line_width) {
+                        check_width(text, line_width)?;
+                    }
+                    if let Some(style) = page.style_mut(line) {
+                        match option {
+                            LineOption::Font(font) => style.font = font,
+                            LineOption::Align(align) => style.align = align,
+                        }
+                        Ok(QueryData::None)
+                    } else {
+                        Err(OperationError::LineOutOfPage(line, page.line_num()))
+                    }
+                } else {
+                    Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::SetExpiry{ page, line, ttl } => {
+                let i = locate(content, page)?;
+                let at = if ttl > 0 { Some(Instant::now() + Duration::from_millis(ttl as u64)) } else { None };
+                if content.set_expiry(i, line, at) {
+                    Ok(QueryData::None)
+                } else if let Some(p) = content.get(i) {
+                    Err(OperationError::LineOutOfPage(line.unwrap_or(0), p.line_num()))
+                } else {
+                    Err(OperationError::PageOutOfBound(i, content.len()))
+                }
+            },
+            Self::QueryLayout => {
+                Ok(QueryData::Layout{ lines: content.line_limit(), columns: content.line_width() })
+            },
+            Self::Alert{ .. } | Self::Acknowledge | Self::QueryStatus | Self::Reload => {
                 Err(OperationError::Invalid)
+            },
+            Self::QueryPage{ page } => {
+                let i = locate(content, page)?;
+                match content.get(i) {
+                    Some(Page::Empty) => Ok(QueryData::Lines(&[])),
+                    Some(Page::Text{ lines, .. }) => Ok(QueryData::Lines(lines)),
+                    Some(Page::BImage{ data, w, h }) => Ok(QueryData::Image{ data, w: *w, h: *h }),
+                    Some(Page::Chart(chart)) => Ok(QueryData::Samples(&chart.samples)),
+                    None => Err(OperationError::PageOutOfBound(i, content.len()))
+                }
             }
         }
     }
 }
 
+/**
+ * index of an existing page, numbers are passed through for the bound check of the caller
+ */
+fn locate(content: &Content, page: PageRef) -> Result<usize, OperationError> {
+    match page {
+        PageRef::Index(i) => Ok(i),
+        PageRef::Name(name) => content.find(&name).ok_or(OperationError::PageNotFound(name)),
+    }
+}
+
+fn check_width(text: &str, line_width: usize) -> Result<(), OperationError> {
+    let len = text.chars().count();
+    if len > line_width {
+        Err(OperationError::TextTooLong(len, line_width))
+    } else {
+        Ok(())
+    }
+}
+
+/**
+ * try `change` on a copy of page `i` first, it is refused if the lines would no longer fit on the display
+ */
+fn check_height<F: FnOnce(&mut Page) -> bool>(content: &Content, i: usize, change: F) -> Result<(), OperationError> {
+    if let Some(page) = content.get(i) {
+        let mut page = page.clone();
+        if change(&mut page) {
+            content.check_height(&page)
+                .map_err(|(height, room)| OperationError::TextTooTall(height as usize, room as usize))?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * like `locate`, but an unknown name points past the last page so it gets appended,
+ * together with the name to give it once it is there
+ */
+fn locate_or_add(content: &Content, page: PageRef) -> (usize, Option<String>) {
+    match page {
+        PageRef::Index(i) => (i, None),
+        PageRef::Name(name) => match content.find(&name) {
+            Some(i) => (i, None),
+            None => (content.len(), Some(name)),
+        }
+    }
+}
+
 pub struct CommandSplit<'a> {
     data: &'a [u8],
     index: &'a mut usize,
@@ -297,14 +977,47 @@ impl<'a> Iterator for CommandSplit<'a> {
 pub enum QueryData<'a> {
     None,
     Text(&'a str),
+    Lines(&'a [String]),
+    Image {
+        data: &'a [u8],
+        w: u32,
+        h: u32,
+    },
+    Layout {
+        lines: usize,
+        columns: usize,
+    },
+    Samples(&'a VecDeque<f32>),
 }
 
-impl<'a> QueryData<'a> {
-    pub fn get_text(&'a self) -> &'a str {
-        if let Self::Text(s) = self {
-            s
-        } else {
-            ""
+/**
+ * single values are answered as `+<text>\r\n`,
+ * multiple lines as `*<count>\r\n` followed by one `+<line>\r\n` each,
+ * images in the same `+<w>,<h>:<base64>\r\n` form they are set with,
+ * the layout as `*2\r\n+lines:<n>\r\n+columns:<n>\r\n`,
+ * samples like lines from the oldest to the newest
+ */
+impl<'a> fmt::Display for QueryData<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "+\r\n"),
+            Self::Text(s) => write!(f, "+{}\r\n", s),
+            Self::Lines(lines) => {
+                write!(f, "*{}\r\n", lines.len())?;
+                for line in lines.iter() {
+                    write!(f, "+{}\r\n", line)?;
+                }
+                Ok(())
+            },
+            Self::Image{ data, w, h } => write!(f, "+{},{}:{}\r\n", w, h, base64::encode(data)),
+            Self::Layout{ lines, columns } => write!(f, "*2\r\n+lines:{}\r\n+columns:{}\r\n", lines, columns),
+            Self::Samples(samples) => {
+                write!(f, "*{}\r\n", samples.len())?;
+                for value in samples.iter() {
+                    write!(f, "+{}\r\n", contents::format_sample(*value))?;
+                }
+                Ok(())
+            },
         }
     }
 }
@@ -314,6 +1027,8 @@ impl<'a> QueryData<'a> {
 #[derive(Debug)]
 pub enum ParseError {
     InvalidLine,
+    LineTooLong,
+    #[allow(dead_code)]
     UnexpectedEnd(usize),
     InvalidData(usize),
     InvalidToken(usize),
@@ -324,10 +1039,11 @@ impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::InvalidLine => write!(f, "InvalidLine"),
+            Self::LineTooLong => write!(f, "LineTooLong"),
             Self::UnexpectedEnd(i) => write!(f, "UnexpectedEnd({})", i),
             Self::InvalidData(i) => write!(f, "InvalidData({})", i),
             Self::InvalidToken(i) => write!(f, "InvalidToken({})", i),
-            Self::InvalidBase64(i, e) => write!(f, "InvalidBase64({})", i),
+            Self::InvalidBase64(i, e) => write!(f, "InvalidBase64({}):{}", i, e),
         }
     }
 }
@@ -342,7 +1058,13 @@ impl error::Error for ParseError {
 pub enum OperationError {
     Invalid,
     PageOutOfBound(usize, usize),
-    LineOutOfPage(usize, usize)
+    LineOutOfPage(usize, usize),
+    ImageSizeMismatch(usize, usize),
+    PageNotFound(String),
+    NameInUse(String),
+    TextTooLong(usize, usize),
+    TextTooTall(usize, usize),
+    NotAChart(usize),
 }
 
 impl fmt::Display for OperationError {
@@ -351,10 +1073,84 @@ impl fmt::Display for OperationError {
             Self::Invalid => write!(f, "Invalid"),
             Self::PageOutOfBound(i, n) => write!(f, "PageOutOfBound:{}@{}", i, n),
             Self::LineOutOfPage(i, n) => write!(f, "LineOutOfPage:{}@{}", i, n),
+            Self::ImageSizeMismatch(i, n) => write!(f, "ImageSizeMismatch:{}@{}", i, n),
+            Self::PageNotFound(name) => write!(f, "PageNotFound:{}", name),
+            Self::NameInUse(name) => write!(f, "NameInUse:{}", name),
+            Self::TextTooLong(i, n) => write!(f, "TextTooLong:{}@{}", i, n),
+            Self::TextTooTall(i, n) => write!(f, "TextTooTall:{}@{}", i, n),
+            Self::NotAChart(i) => write!(f, "NotAChart:{}", i),
         }
     }
 }
 
 impl error::Error for OperationError {
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Operation, ParseError> {
+        Operation::new(format!("{}\r\n", line))
+    }
+
+    #[test]
+    fn refuses_images_too_large() {
+        assert!(matches!(parse("@0+524288,65536:"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@0+1025,1:"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@0+8,1:AA=="), Ok(Operation::SetPage{ width: 8, height: 1, .. })));
+    }
+
+    #[test]
+    fn refuses_lines_without_crlf() {
+        assert!(matches!(Operation::new(b"@1:2+x\n"), Err(ParseError::InvalidLine)));
+        assert!(matches!(Operation::new(b"\n"), Err(ParseError::InvalidLine)));
+    }
+
+    #[test]
+    fn parses_named_pages() {
+        assert!(matches!(parse("@cpu:0+12%"),
+            Ok(Operation::SetText{ page: PageRef::Name(name), line: 0, .. }) if name == "cpu"));
+        assert!(matches!(parse("@12:0+x"), Ok(Operation::SetText{ page: PageRef::Index(12), .. })));
+    }
+
+    #[test]
+    fn parses_negative_bar_ranges() {
+        match parse("@1:2%-5,-20,40:temp") {
+            Ok(Operation::SetBar{ value, range, label, .. }) => {
+                assert_eq!(value, -5);
+                assert_eq!(range, Some((-20, 40)));
+                assert_eq!(label.as_deref(), Some("temp"));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(parse("@1:2%42"), Ok(Operation::SetBar{ value: 42, range: None, label: None, .. })));
+    }
+
+    #[test]
+    fn refuses_empty_and_unclosed_bar_ranges() {
+        assert!(matches!(parse("@1:2%5,40,-20"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@1:2%5,10,10"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@1:2%5,-20"), Err(ParseError::InvalidToken(_))));
+        assert!(matches!(parse("@1:2%-"), Err(ParseError::InvalidData(_))));
+    }
+
+    #[test]
+    fn refuses_numbers_that_overflow() {
+        assert!(matches!(parse("@0=99999999999999999999999"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@0=4294967296"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@0=4294967295"), Ok(Operation::SetDwell{ dwell: 4294967295, .. })));
+        assert!(matches!(parse("@99999999999999999999999:0+x"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@0+99999999999999999999999,1:"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@1:2%2147483648"), Err(ParseError::InvalidData(_))));
+        assert!(matches!(parse("@1:2%-2147483647"), Ok(Operation::SetBar{ value: -2147483647, .. })));
+    }
+
+    #[test]
+    fn parses_text_with_markup_as_it_is() {
+        match parse("@1:2+error {ib:42") {
+            Ok(Operation::SetText{ page: PageRef::Index(1), line: 2, text }) => assert_eq!(text, "error {ib:42"),
+            other => panic!("{:?}", other),
+        }
+    }
+}
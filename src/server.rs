@@ -10,7 +10,9 @@ use async_std::io::prelude::WriteExt as _;
 use async_std::net::TcpListener;
 use async_std::net::TcpStream;
 use async_std::net::ToSocketAddrs;
-use async_std::stream;
+use async_std::channel;
+use async_std::future;
+use futures::stream;
 use futures::stream::Stream;
 use futures::stream::StreamExt as _;
 use futures::task::Context;
@@ -60,21 +62,22 @@ impl Buf {
         self.buf.len() - self.write
     }
 
-    pub async fn write_from_reader<'a, R: ARead + Unpin>(&mut self, src: &'a mut R) -> aio::Result<usize> {
+    pub async fn write_from_reader<R: ARead + Unpin>(&mut self, src: &mut R) -> aio::Result<usize> {
         let slice = unsafe { self.buf.get_unchecked_mut(self.write..) };
         let len = src.read(slice).await?;
         self.write += len;
         Ok(len)
     }
 
-    pub async fn read_to_writer<'a, W: AWrite + Unpin>(&mut self, tgt: &'a mut W) -> aio::Result<usize> {
+    #[allow(dead_code)]
+    pub async fn read_to_writer<W: AWrite + Unpin>(&mut self, tgt: &mut W) -> aio::Result<usize> {
         let slice = unsafe { self.buf.get_unchecked_mut(self.read .. self.write) };
         let len = tgt.write(slice).await?;
         self.read += len;
         Ok(len)
     }
 
-    pub async fn read_all_to_writer<'a, W: AWrite + Unpin>(&mut self, tgt: &'a mut W) -> aio::Result<usize> {
+    pub async fn read_all_to_writer<W: AWrite + Unpin>(&mut self, tgt: &mut W) -> aio::Result<usize> {
         let slice = unsafe { self.buf.get_unchecked_mut(self.read .. self.write) };
         tgt.write_all(slice).await?;
         let len = self.write - self.read;
@@ -82,7 +85,7 @@ impl Buf {
         Ok(len)
     }
 
-    pub fn get<'a>(&'a self, len: usize) -> &'a [u8] {
+    pub fn get(&self, len: usize) -> &[u8] {
         let i = self.read;
         let j = std::cmp::min(i + len, self.write);
         unsafe{ self.buf.get_unchecked(i..j) }
@@ -96,7 +99,7 @@ impl Buf {
         let count = self.write - self.read;
         if count > 0 {
             unsafe {
-                let src = self.buf.as_ptr().offset(self.read as isize);
+                let src = self.buf.as_ptr().add(self.read);
                 let dst = self.buf.as_mut_ptr();
                 std::ptr::copy(src, dst, count);
             }
@@ -115,40 +118,63 @@ impl Buf {
 pub trait Handler {
     fn handle_network(&self, read: &mut Buf) -> aio::Result<Buf>;
     fn handle_schedule(&self) -> aio::Result<()>;
+    fn handle_reload(&self) -> aio::Result<()>;
+    /// the answer to a line that does not fit in the buffer, the rest of it is dropped
+    fn handle_overflow(&self) -> Buf;
+    /// how long until `handle_schedule` has something to do
+    fn next_schedule(&self) -> Duration;
 }
 
 pub struct Server<H: Handler> {
     n_worker: usize,
     buf_size: usize,
     handler: H,
-    interval: Duration,
 }
 
 impl<H: Handler> Server<H> {
 
-    pub fn new(handler: H, interval: Duration, buf_size: usize, n_worker: usize) -> Self {
+    pub fn new(handler: H, buf_size: usize, n_worker: usize) -> Self {
         Server {
             n_worker: n_worker + 1,
             buf_size,
             handler,
-            interval
         }
     }
 
-    pub async fn start_server(&self, addr: impl ToSocketAddrs) -> aio::Result<()> {
+    /**
+     * `reloads` yields whenever the handler should reload its settings (e.g. on SIGHUP);
+     * the schedule sleeps until the handler's next page is due, asking again after every tick
+     * and whenever a request or reload may have changed it
+     */
+    pub async fn start_server<R>(&self, addr: impl ToSocketAddrs, reloads: R) -> aio::Result<()>
+    where
+        R: Stream<Item=()> + Unpin
+    {
 
-        let scheduler = stream::interval(self.interval);
+        let (wake, woken) = channel::bounded::<()>(1);
+        let woken = &woken;
+        let wake = &wake;
+        let scheduler = Box::pin(stream::unfold((), move |_| async move {
+            let _ = future::timeout(self.handler.next_schedule(), woken.recv()).await;
+            Some(((), ()))
+        }));
         let listener = TcpListener::bind(addr).await?;
-        CombinedStream::new(scheduler, listener.incoming())
+        CombinedStream::new(CombinedStream::new(scheduler, reloads), listener.incoming())
             .for_each_concurrent(Some(self.n_worker), |stream| async move {
                 match stream {
-                    CombinedStreamOutput::First(_) => {
+                    CombinedStreamOutput::First(CombinedStreamOutput::First(_)) => {
                         if let Err(e) = self.schedule_job().await {
                             eprintln!("{}", e)
                         }
                     },
+                    CombinedStreamOutput::First(CombinedStreamOutput::Second(_)) => {
+                        if let Err(e) = self.handler.handle_reload() {
+                            eprintln!("{}", e)
+                        }
+                        let _ = wake.try_send(());
+                    },
                     CombinedStreamOutput::Second(stream) => {
-                        if let Err(e) = self.process(stream).await {
+                        if let Err(e) = self.process(stream, wake).await {
                             eprintln!("{}", e)
                         }
                     }
@@ -158,18 +184,40 @@ impl<H: Handler> Server<H> {
         Ok(())
     }
 
-    async fn process(&self, stream: aio::Result<TcpStream>) -> aio::Result<()> {
+    async fn process(&self, stream: aio::Result<TcpStream>, wake: &channel::Sender<()>) -> aio::Result<()> {
         let mut stream = stream?;
         let mut buf = Buf::new(self.buf_size);
         let handler = &self.handler;
+        // skipping to the end of a line that did not fit
+        let mut overflow = false;
         loop {
             let c = buf.write_from_reader(&mut stream).await?;
             if c == 0 {
                 break;
             }
+            if overflow {
+                match find_crlf(buf.get(buf.readable())) {
+                    Some(j) => {
+                        buf.skip(j + 2);
+                        overflow = false;
+                    }
+                    None => {
+                        // keep the last byte, it may be the CR of a CRLF split between reads
+                        buf.skip(buf.readable() - 1);
+                        buf.flip();
+                        continue;
+                    }
+                }
+            }
             let mut response = handler.handle_network(&mut buf)?;
+            let _ = wake.try_send(());
             buf.flip();
             response.read_all_to_writer(&mut stream).await?;         
+            if buf.writeable() == 0 {
+                buf.reset();
+                overflow = true;
+                handler.handle_overflow().read_all_to_writer(&mut stream).await?;
+            }
         }
         Ok(())
     }
@@ -180,6 +228,9 @@ impl<H: Handler> Server<H> {
     }
 }
 
+fn find_crlf(data: &[u8]) -> Option<usize> {
+    data.windows(2).position(|w| w == b"\r\n")
+}
 
 pub enum CombinedStreamOutput<T1, T2> {
     First(T1),
@@ -217,10 +268,8 @@ where
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self;
-        let mut ready = false;
         if !this.finished1 {
             if let Poll::Ready(it) = Stream::poll_next(Pin::new(&mut this.s1), cx) {
-                ready = true;
                 if let Some(v) = it {
                     return Poll::Ready(Some(CombinedStreamOutput::First(v)));
                 } else {
@@ -230,7 +279,6 @@ where
         }
         if !this.finished2 {
             if let Poll::Ready(it) = Stream::poll_next(Pin::new(&mut this.s2), cx) {
-                ready = true;
                 if let Some(v) = it {
                     return Poll::Ready(Some(CombinedStreamOutput::Second(v)));
                 } else {
@@ -238,7 +286,8 @@ where
                 }            
             }    
         }
-        if ready {
+        // only end once both ends are done, a finished side must not stop the other one
+        if this.finished1 && this.finished2 {
             Poll::Ready(None)
         } else {
             Poll::Pending
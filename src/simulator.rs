@@ -0,0 +1,186 @@
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+use embedded_graphics::prelude::*;
+use embedded_graphics::pixelcolor::BinaryColor;
+use async_std::io as aio;
+use crate::manager::Canvas;
+use crate::contents::Page;
+use crate::contents::TextFont;
+use crate::display::draw_page;
+
+/**
+ * in-memory 1-bit framebuffer, rows padded to whole bytes with the MSB as the leftmost pixel
+ * (the same layout as `Page::BImage`, PBM `P4` and 1-bit grayscale PNG)
+ */
+pub struct FrameBuffer {
+    data: Box<[u8]>,
+    width: u32,
+    height: u32,
+}
+
+impl FrameBuffer {
+
+    pub fn new(width: u32, height: u32) -> Self {
+        FrameBuffer {
+            data: vec![0u8; Page::image_size(width, height).expect("display size")].into_boxed_slice(),
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        if x < self.width && y < self.height {
+            let (i, bit) = self.locate(x, y);
+            self.data[i] & bit != 0
+        } else {
+            false
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for b in self.data.iter_mut() {
+            *b = 0;
+        }
+    }
+
+    fn locate(&self, x: u32, y: u32) -> (usize, u8) {
+        let stride = self.width.div_ceil(8);
+        ((y * stride + x / 8) as usize, 0x80 >> (x % 8))
+    }
+}
+
+impl DrawTarget<BinaryColor> for FrameBuffer {
+    type Error = Infallible;
+
+    fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> Result<(), Self::Error> {
+        let Pixel(p, color) = pixel;
+        if p.x < 0 || p.y < 0 || p.x as u32 >= self.width || p.y as u32 >= self.height {
+            return Ok(());
+        }
+        let (i, bit) = self.locate(p.x as u32, p.y as u32);
+        match color {
+            BinaryColor::On => self.data[i] |= bit,
+            BinaryColor::Off => self.data[i] &= !bit,
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SnapshotFormat {
+    Png,
+    Pbm,
+}
+
+impl SnapshotFormat {
+
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?;
+        if ext.eq_ignore_ascii_case("png") {
+            Some(Self::Png)
+        } else if ext.eq_ignore_ascii_case("pbm") {
+            Some(Self::Pbm)
+        } else {
+            None
+        }
+    }
+}
+
+/**
+ * hardware-free canvas: renders like `SSD1306Display` and writes a snapshot file on every flush
+ */
+pub struct SimulatorDisplay {
+    buffer: FrameBuffer,
+    font: TextFont,
+    line_interval: i32,
+    invert: bool,
+    path: PathBuf,
+    format: SnapshotFormat,
+}
+
+impl Canvas for SimulatorDisplay {
+
+    fn draw(&mut self, page: &Page) -> aio::Result<()> {
+        draw_page(&mut self.buffer, page, self.font, self.line_interval, self.invert)
+            .map_err(|e| aio::Error::other(format!("{:?}", e)))
+    }
+
+    fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
+    }
+
+    fn init(&mut self) -> aio::Result<()> {
+        self.buffer.reset();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> aio::Result<()> {
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        match self.format {
+            SnapshotFormat::Png => write_png(&self.buffer, &mut writer)?,
+            SnapshotFormat::Pbm => write_pbm(&self.buffer, &mut writer)?,
+        }
+        writer.flush()
+    }
+
+    fn clear(&mut self) -> aio::Result<()> {
+        self.buffer.reset();
+        Ok(())
+    }
+}
+
+impl SimulatorDisplay {
+
+    pub fn new(width: u32, height: u32, font: TextFont, line_interval: u32, path: PathBuf, format: SnapshotFormat) -> Self {
+        SimulatorDisplay {
+            buffer: FrameBuffer::new(width, height),
+            font,
+            line_interval: line_interval as i32,
+            invert: false,
+            path,
+            format,
+        }
+    }
+}
+
+/**
+ * lit pixels are written as white, like on the panel
+ */
+fn write_png<W: std::io::Write>(buffer: &FrameBuffer, writer: W) -> aio::Result<()> {
+    let mut encoder = png::Encoder::new(writer, buffer.width(), buffer.height());
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+    let mut writer = encoder.write_header()
+        .map_err(|e| aio::Error::other(format!("{}", e)))?;
+    writer.write_image_data(buffer.data())
+        .map_err(|e| aio::Error::other(format!("{}", e)))
+}
+
+/**
+ * PBM stores 1 as black, so the bits are inverted to keep lit pixels white
+ */
+fn write_pbm<W: std::io::Write>(buffer: &FrameBuffer, mut writer: W) -> aio::Result<()> {
+    write!(writer, "P4\n{} {}\n", buffer.width(), buffer.height())?;
+    let data: Vec<u8> = buffer.data().iter().map(|b| !b).collect();
+    writer.write_all(&data)
+}
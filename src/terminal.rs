@@ -0,0 +1,90 @@
+use std::io::Write as _;
+use async_std::io as aio;
+use crate::manager::Canvas;
+use crate::contents::Page;
+use crate::contents::TextFont;
+use crate::display::draw_page;
+use crate::simulator::FrameBuffer;
+
+const ESC_CLEAR: &str = "\x1b[2J";
+const ESC_HOME: &str = "\x1b[H";
+
+/**
+ * live preview in the terminal: every character cell shows two pixel rows with half blocks
+ */
+pub struct TerminalDisplay {
+    buffer: FrameBuffer,
+    font: TextFont,
+    line_interval: i32,
+    invert: bool,
+}
+
+impl Canvas for TerminalDisplay {
+
+    fn draw(&mut self, page: &Page) -> aio::Result<()> {
+        draw_page(&mut self.buffer, page, self.font, self.line_interval, self.invert)
+            .map_err(|e| aio::Error::other(format!("{:?}", e)))
+    }
+
+    fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
+    }
+
+    fn init(&mut self) -> aio::Result<()> {
+        self.buffer.reset();
+        print!("{}", ESC_CLEAR);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> aio::Result<()> {
+        let frame = self.render();
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(frame.as_bytes())?;
+        out.flush()
+    }
+
+    fn clear(&mut self) -> aio::Result<()> {
+        self.buffer.reset();
+        Ok(())
+    }
+}
+
+impl TerminalDisplay {
+
+    pub fn new(width: u32, height: u32, font: TextFont, line_interval: u32) -> Self {
+        TerminalDisplay {
+            buffer: FrameBuffer::new(width, height),
+            font,
+            line_interval: line_interval as i32,
+            invert: false,
+        }
+    }
+
+    fn render(&self) -> String {
+        let buffer = &self.buffer;
+        let (w, h) = (buffer.width(), buffer.height());
+        let mut frame = String::with_capacity(((w + 3) * (h / 2 + 2) * 3) as usize);
+        frame.push_str(ESC_HOME);
+        frame.push('┌');
+        (0 .. w).for_each(|_x| frame.push('─'));
+        frame.push_str("┐\n");
+        for y in (0 .. h).step_by(2) {
+            frame.push('│');
+            for x in 0 .. w {
+                let c = match (buffer.get(x, y), buffer.get(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                };
+                frame.push(c);
+            }
+            frame.push_str("│\n");
+        }
+        frame.push('└');
+        (0 .. w).for_each(|_x| frame.push('─'));
+        frame.push_str("┘\n");
+        frame
+    }
+}